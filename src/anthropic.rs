@@ -0,0 +1,525 @@
+use crate::{
+    error::AirError,
+    ichat::{Content, ContentPart, IChat, Message, Role, Sampling, ToolCall, Usage},
+    output::Output,
+    retry::Retry,
+    sse::{SseDecoder, SseEvent},
+    tools::{ToolRegistry, MAX_ROUNDS},
+};
+use async_trait::async_trait;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio_stream::StreamExt;
+
+// https://docs.anthropic.com/claude/reference/messages-streaming
+
+const HEADER_API_KEY: &str = "x-api-key";
+const HEADER_VERSION: &str = "anthropic-version";
+const HEADER_CONTENT_TYPE: &str = "Content-Type";
+const CONTENT_TYPE_JSON: &str = "application/json";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MESSAGES: &str = "/messages";
+
+#[derive(Serialize, Debug)]
+pub struct AnthropicCompletion<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    messages: &'a Vec<AnthropicMessage>,
+    stream: bool,
+    #[serde(flatten)]
+    sampling: Sampling,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<AnthropicTool>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AnthropicTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContent {
+    Text {
+        text: String,
+    },
+    Image {
+        source: AnthropicImage,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+}
+
+#[derive(Serialize, Debug)]
+pub struct AnthropicImage {
+    #[serde(rename = "type")]
+    source_type: String,
+    media_type: String,
+    data: String,
+}
+
+/// Messages api format : content blocks, tool results are sent by the user.
+#[derive(Serialize, Debug)]
+pub struct AnthropicMessage {
+    role: Role,
+    content: Vec<AnthropicContent>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicDelta {
+    #[serde(rename = "type")]
+    delta_type: Option<String>,
+    text: Option<String>,
+    partial_json: Option<String>,
+    // message_delta
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    id: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicStart {
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicError {
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    message: Option<String>,
+}
+
+impl AnthropicError {
+    fn to_error(&self, status: Option<StatusCode>) -> AirError {
+        let error_type = self.error_type.as_deref().unwrap_or("error");
+        let message = format!(
+            "{} (type: {})",
+            self.message.as_deref().unwrap_or("Unknown error"),
+            error_type
+        );
+        match error_type {
+            "authentication_error" | "permission_error" => AirError::Auth(message),
+            "not_found_error" => AirError::ModelLoad(message),
+            "rate_limit_error" => AirError::RateLimit(message),
+            _ => match status {
+                Some(status) => AirError::from_status(status, message),
+                None => AirError::Backend(message),
+            },
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct AnthropicEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    index: Option<u32>,
+    delta: Option<AnthropicDelta>,
+    // content_block_start
+    content_block: Option<AnthropicBlock>,
+    // message_start
+    message: Option<AnthropicStart>,
+    // message_delta
+    usage: Option<AnthropicUsage>,
+    error: Option<AnthropicError>,
+}
+
+pub struct Anthropic {
+    pub apikey: String,
+    pub model: String,
+    pub system: Option<String>,
+    pub base_url: String,
+    pub max_tokens: u32,
+    pub retry: Retry,
+    pub usage: Option<Usage>,
+    pub tools: Option<ToolRegistry>,
+    pub images: Vec<ContentPart>,
+    pub output: Output,
+    pub finish_reason: Option<String>,
+    pub sampling: Sampling,
+}
+
+/// What a single request streamed back.
+#[derive(Default)]
+struct Answer {
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    usage: Option<Usage>,
+    finish_reason: Option<String>,
+}
+
+/// Text and images (data urls) blocks.
+fn to_blocks(content: Content) -> Vec<AnthropicContent> {
+    let parts = match content {
+        Content::Text(text) if text.is_empty() => vec![],
+        Content::Text(text) => vec![ContentPart::Text { text }],
+        Content::Parts(parts) => parts,
+    };
+    let mut blocks = vec![];
+    for part in parts {
+        match part {
+            ContentPart::Text { text } => blocks.push(AnthropicContent::Text { text }),
+            ContentPart::ImageUrl { image_url } => match image_url.get_data() {
+                Some((media_type, data)) => blocks.push(AnthropicContent::Image {
+                    source: AnthropicImage {
+                        source_type: "base64".to_string(),
+                        media_type: media_type.to_string(),
+                        data: data.to_string(),
+                    },
+                }),
+                None => log::warn!("Skipping image, not a base64 data url"),
+            },
+        }
+    }
+    return blocks;
+}
+
+/// Convert to content blocks, merging consecutive messages of the same
+/// role (ie: several tool results) as roles must alternate.
+fn to_anthropic(messages: Vec<Message>) -> Vec<AnthropicMessage> {
+    let mut converted: Vec<AnthropicMessage> = vec![];
+    for message in messages {
+        let (role, mut content) = match message.role {
+            // system prompt is not a message role in the messages api
+            Role::System => continue,
+            Role::Tool => (
+                Role::User,
+                vec![AnthropicContent::ToolResult {
+                    tool_use_id: message.tool_call_id.unwrap_or_default(),
+                    content: message.content.text(),
+                }],
+            ),
+            role => {
+                let mut content = to_blocks(message.content);
+                for call in message.tool_calls.unwrap_or_default() {
+                    content.push(AnthropicContent::ToolUse {
+                        id: call.id,
+                        name: call.function.name,
+                        input: serde_json::from_str(&call.function.arguments)
+                            .unwrap_or(serde_json::json!({})),
+                    });
+                }
+                (role, content)
+            }
+        };
+        match converted.last_mut() {
+            Some(last) if last.role == role => last.content.append(&mut content),
+            _ => converted.push(AnthropicMessage { role, content }),
+        }
+    }
+    return converted;
+}
+
+/// Stop reason named as OpenAI does, the same for every backend.
+fn to_finish_reason(reason: String) -> String {
+    return match reason.as_str() {
+        "end_turn" | "stop_sequence" => "stop".to_string(),
+        "max_tokens" => "length".to_string(),
+        "tool_use" => "tool_calls".to_string(),
+        _ => reason,
+    };
+}
+
+fn parse_data(json: AnthropicEvent, answer: &mut Answer, output: Output) -> Result<(), AirError> {
+    let usage = &mut answer.usage;
+
+    match json.event_type.as_str() {
+        "content_block_start" => {
+            if let Some(block) = json.content_block {
+                if block.block_type == "tool_use" {
+                    answer.tool_calls.push(ToolCall {
+                        id: block.id.unwrap_or_default(),
+                        call_type: "function".to_string(),
+                        ..Default::default()
+                    });
+                    let call = answer.tool_calls.last_mut().unwrap();
+                    call.function.name = block.name.unwrap_or_default();
+                }
+            }
+        }
+        "content_block_delta" => {
+            if let Some(delta) = json.delta {
+                match delta.delta_type.as_deref() {
+                    Some("text_delta") => {
+                        if let Some(text) = delta.text {
+                            output.token(&text);
+                            answer.content.push_str(&text);
+                        }
+                    }
+                    // arguments of the tool_use block being streamed
+                    Some("input_json_delta") => {
+                        if let Some(call) = answer.tool_calls.last_mut() {
+                            call.function
+                                .arguments
+                                .push_str(&delta.partial_json.unwrap_or_default());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        "message_start" => {
+            if let Some(u) = json.message.and_then(|m| m.usage) {
+                *usage = Some(Usage {
+                    prompt_tokens: u.input_tokens.unwrap_or(0),
+                    completion_tokens: u.output_tokens.unwrap_or(0),
+                });
+            }
+        }
+        "message_delta" => {
+            // output_tokens is cumulative
+            if let Some(u) = json.usage {
+                let current = usage.get_or_insert(Usage::default());
+                current.completion_tokens = u.output_tokens.unwrap_or(current.completion_tokens);
+            }
+            if let Some(reason) = json.delta.and_then(|d| d.stop_reason) {
+                answer.finish_reason = Some(to_finish_reason(reason));
+            }
+        }
+        "message_stop" => {
+            output.end();
+        }
+        "error" => {
+            if let Some(error) = json.error {
+                return Err(error.to_error(None));
+            }
+        }
+        // content_block_stop
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Append the content of a stream event to `message`, a malformed event
+/// is logged and skipped so it doesn't abort the whole answer, an error
+/// event does.
+fn handle_event(event: &SseEvent, answer: &mut Answer, output: Output) -> Result<(), AirError> {
+    if event.event == "ping" {
+        return Ok(());
+    }
+    let json: AnthropicEvent = match serde_json::from_str(&event.data) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!(
+                "Skipping malformed event '{}' ({}) : {}",
+                event.event,
+                e,
+                event.data
+            );
+            return Ok(());
+        }
+    };
+    parse_data(json, answer, output)
+}
+
+/// Decode the error object of a non 2xx response.
+async fn get_error(response: Response) -> AirError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    log::debug!("{} : {}", status, body);
+
+    match serde_json::from_str::<AnthropicEvent>(&body) {
+        Ok(AnthropicEvent {
+            error: Some(error), ..
+        }) => error.to_error(Some(status)),
+        _ => AirError::from_status(status, format!("{} {}", status, body.trim())),
+    }
+}
+
+#[async_trait]
+impl IChat for Anthropic {
+    fn get_name(&mut self) -> &str {
+        return "anthropic";
+    }
+
+    fn set_system(&mut self, system: String) {
+        self.system = Some(system)
+    }
+
+    fn set_model(&mut self, model: String) {
+        self.model = model
+    }
+
+    fn get_model(&self) -> String {
+        return self.model.clone();
+    }
+
+    fn get_usage(&self) -> Option<Usage> {
+        return self.usage;
+    }
+
+    fn get_finish_reason(&self) -> Option<String> {
+        return self.finish_reason.clone();
+    }
+
+    fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = Some(tools);
+    }
+
+    fn set_images(&mut self, images: Vec<ContentPart>) {
+        self.images = images;
+    }
+
+    async fn chat(
+        &mut self,
+        prompt: String,
+        history: Option<Vec<Message>>,
+    ) -> Result<String, AirError> {
+        let mut messages: Vec<Message> = history.unwrap_or_default();
+        messages.push(Message::with_images(
+            Role::User,
+            prompt.to_string(),
+            &self.images,
+        ));
+
+        self.usage = None;
+        let mut rounds: usize = 0;
+        loop {
+            let answer = self.send(to_anthropic(messages.clone())).await?;
+            self.finish_reason = answer.finish_reason.clone();
+            if let Some(usage) = &answer.usage {
+                self.usage.get_or_insert(Usage::default()).add(usage);
+            }
+
+            let tools = match &self.tools {
+                Some(tools) if !answer.tool_calls.is_empty() => tools,
+                _ => return Ok(answer.content),
+            };
+            rounds += 1;
+            if rounds > MAX_ROUNDS {
+                return Err(AirError::Backend(format!(
+                    "Still calling tools after {} rounds, giving up.",
+                    MAX_ROUNDS
+                )));
+            }
+
+            let calls = answer.tool_calls;
+            let mut results = vec![];
+            for call in &calls {
+                results.push(Message::tool_result(
+                    call.id.clone(),
+                    tools.call(call).await,
+                ));
+            }
+            messages.push(Message {
+                tool_calls: Some(calls),
+                ..Message::new(Role::Assistant, answer.content)
+            });
+            messages.extend(results);
+        }
+    }
+}
+
+impl Anthropic {
+    pub fn new(apikey: String, base_url: String, max_tokens: u32, retry: Retry) -> Self {
+        return Anthropic {
+            apikey,
+            model: crate::setup::DEFAULT_ANTHROPIC_MODEL.to_string(),
+            system: None,
+            base_url,
+            max_tokens,
+            retry,
+            usage: None,
+            tools: None,
+            images: vec![],
+            output: Output::Text,
+            finish_reason: None,
+            sampling: Sampling::default(),
+        };
+    }
+
+    /// One request, streamed to stdout.
+    async fn send(&self, messages: Vec<AnthropicMessage>) -> Result<Answer, AirError> {
+        let completion = AnthropicCompletion {
+            model: &self.model,
+            max_tokens: self.max_tokens,
+            system: self.system.as_deref().filter(|s| !s.is_empty()),
+            stream: true,
+            sampling: self.sampling,
+            tools: self.tools.as_ref().map(|tools| {
+                tools
+                    .definitions()
+                    .into_iter()
+                    .map(|tool| AnthropicTool {
+                        name: tool.name,
+                        description: tool.description,
+                        input_schema: tool.parameters,
+                    })
+                    .collect()
+            }),
+            messages: &messages,
+        };
+        let serialized: String = serde_json::to_string_pretty(&completion)?;
+        log::debug!("{}\n", serialized);
+
+        let url = format!("{}{}", self.base_url.trim_end_matches('/'), MESSAGES);
+        log::debug!("POST {}", url);
+
+        let client: Client = Client::new();
+        let request = client
+            .post(url)
+            .header(HEADER_API_KEY, &self.apikey)
+            .header(HEADER_VERSION, ANTHROPIC_VERSION)
+            .header(HEADER_CONTENT_TYPE, CONTENT_TYPE_JSON)
+            .body(serialized);
+        let response = self.retry.send(request).await?;
+        if !response.status().is_success() {
+            return Err(get_error(response).await);
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut answer = Answer::default();
+        let mut decoder = SseDecoder::new();
+
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(bytes) => {
+                    for event in decoder.push(&bytes) {
+                        handle_event(&event, &mut answer, self.output)?;
+                    }
+                }
+                Err(e) => {
+                    // a truncated answer isn't an answer
+                    self.output.end();
+                    return Err(AirError::Network(format!("Stream interrupted : {}", e)));
+                }
+            }
+        }
+        for event in decoder.finish() {
+            handle_event(&event, &mut answer, self.output)?;
+        }
+        Ok(answer)
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    error::AirError,
+    export,
+    history::{ArchivedMessage, History, HistoryMessage},
+    ollama::OllamaChat,
+    options::{ConfigAction, Filter, HistoryAction, ModelsAction},
+    path::get_config_path,
+    scan::scan_folder,
+    setup::Setup,
+};
+use regex::Regex;
+use std::fs;
+use termimad::{crossterm::style::Stylize, MadSkin};
+
+const DEFAULT_EDITOR: &str = "vi";
+/// Characters displayed around the first match of a search.
+const EXCERPT: usize = 160;
+
+/// `air history ...`
+pub fn history(action: &HistoryAction, setup: &Setup) -> Result<(), AirError> {
+    let expiration = setup.get_expiration();
+    let get_name = |session: &Option<String>| session.clone().unwrap_or(History::get_current());
+    match action {
+        HistoryAction::List => {
+            let current = History::get_current();
+            for name in History::list()? {
+                let count = History::open(&name, expiration)?.len();
+                if name == current {
+                    println!("* {} ({} messages)", Stylize::bold(name), count);
+                } else {
+                    println!("  {} ({} messages)", name, count);
+                }
+            }
+        }
+        HistoryAction::Show(session) => {
+            let history = History::open(&get_name(session), expiration)?;
+            if history.len() == 0 {
+                println!("No messages.");
+            } else {
+                println!("{}", MadSkin::default().term_text(&history.to_markdown()));
+            }
+        }
+        HistoryAction::Clear(session) => {
+            let mut history = History::open(&get_name(session), expiration)?;
+            history.clear();
+            history.save()?;
+            println!("History cleared.");
+        }
+        HistoryAction::Export(options) => {
+            let (title, system, messages) = if options.all {
+                (
+                    "air : all sessions".to_string(),
+                    None,
+                    History::get_archive()?,
+                )
+            } else {
+                let history = History::open(&get_name(&options.session), expiration)?;
+                let messages = history
+                    .get_messages()
+                    .iter()
+                    .map(|message| ArchivedMessage {
+                        session: history.get_session().to_string(),
+                        message: message.clone(),
+                    })
+                    .collect();
+                let title = format!("air : {} session", history.get_session());
+                (title, history.setup.system.clone(), messages)
+            };
+            let messages: Vec<ArchivedMessage> = messages
+                .into_iter()
+                .filter(|m| is_selected(m, &options.filter))
+                .collect();
+            let transcript = export::render(&messages, options.format, &title, system.as_deref())?;
+            match &options.file {
+                Some(file) => {
+                    fs::write(file, transcript)?;
+                    println!("{} message(s) exported to {}", messages.len(), file);
+                }
+                None => print!("{}", transcript),
+            }
+        }
+        HistoryAction::Rename(name, new_name) => {
+            History::rename(name, new_name)?;
+            println!("Session {} renamed to {}.", name, new_name);
+        }
+        HistoryAction::Delete(name) => {
+            History::delete(name)?;
+            println!("Session {} deleted.", name);
+        }
+        HistoryAction::Search(terms, filter) => {
+            let text = terms
+                .iter()
+                .map(|t| t.to_lowercase())
+                .collect::<Vec<String>>();
+            let terms: Vec<String> = terms.iter().map(|t| regex::escape(t)).collect();
+            let pattern = Regex::new(&format!("(?i){}", terms.join("|")))
+                .map_err(|e| AirError::Usage(e.to_string()))?;
+            let hits: Vec<ArchivedMessage> = History::get_archive()?
+                .into_iter()
+                .filter(|hit| is_selected(hit, filter) && is_hit(&hit.message, &text))
+                .collect();
+            for hit in &hits {
+                let message = &hit.message;
+                println!(
+                    "{}  {}  {}/{}  {}",
+                    Stylize::bold(message.get_id()),
+                    message.date.format("%Y-%m-%d %H:%M"),
+                    message.chat,
+                    message.model.as_deref().unwrap_or("?"),
+                    hit.session
+                );
+                println!("  > {}", highlight(&message.user, &pattern));
+                println!("  < {}\n", highlight(&message.assistant, &pattern));
+            }
+            match hits.len() {
+                0 => println!("No messages."),
+                count => println!("{} message(s), continue with : air -R <id> <prompt>", count),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Dates, backend (or model) and session filters.
+fn is_selected(archived: &ArchivedMessage, filter: &Filter) -> bool {
+    let message = &archived.message;
+    let date = message.date.date();
+    if filter.since.map(|since| date < since).unwrap_or(false)
+        || filter.until.map(|until| date > until).unwrap_or(false)
+        || filter
+            .session
+            .as_ref()
+            .map(|s| *s != archived.session)
+            .unwrap_or(false)
+    {
+        return false;
+    }
+    if let Some(backend) = &filter.backend {
+        let model = message.model.as_deref().unwrap_or("");
+        if !message.chat.eq_ignore_ascii_case(backend)
+            && !model.to_lowercase().contains(&backend.to_lowercase())
+        {
+            return false;
+        }
+    }
+    return true;
+}
+
+/// Every (lowercase) term in the prompt or the answer.
+fn is_hit(message: &HistoryMessage, terms: &[String]) -> bool {
+    let text = format!("{}\n{}", message.user, message.assistant).to_lowercase();
+    return terms.iter().all(|term| text.contains(term));
+}
+
+/// One line around the first match, the terms highlighted.
+fn highlight(text: &str, pattern: &Regex) -> String {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let position = pattern.find(&text).map(|m| m.start()).unwrap_or(0);
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let index = chars.iter().position(|(i, _)| *i >= position).unwrap_or(0);
+    let start = index.saturating_sub(EXCERPT / 4);
+    let end = (start + EXCERPT).min(chars.len());
+    let mut excerpt: String = chars[start..end].iter().map(|(_, c)| c).collect();
+    if start > 0 {
+        excerpt.insert_str(0, "...");
+    }
+    if end < chars.len() {
+        excerpt.push_str("...");
+    }
+    return pattern
+        .replace_all(&excerpt, |caps: &regex::Captures| {
+            Stylize::yellow(caps[0].to_string()).bold().to_string()
+        })
+        .to_string();
+}
+
+/// `air models ...`
+pub async fn models(action: &ModelsAction, setup: &mut Setup) -> Result<(), AirError> {
+    match action {
+        ModelsAction::List => {
+            println!("Available models:");
+            for llm in setup.local.as_deref().unwrap_or_default() {
+                let exists = if llm.model_exist(llm) {
+                    Stylize::green("✓").to_string()
+                } else {
+                    Stylize::red("✗").to_string()
+                };
+                println!("  - {} {}", llm.name, exists);
+            }
+            if let Some(ollama) = &setup.ollama {
+                match OllamaChat::list_models(ollama).await {
+                    Ok(models) => {
+                        for model in models {
+                            println!("  - {} {}", model, Stylize::blue("(ollama)"));
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Ollama : {}", e);
+                    }
+                }
+            }
+        }
+        ModelsAction::Scan(folder) => scan_folder(setup, folder),
+        ModelsAction::Info(name) => {
+            let local = setup.local.as_deref().unwrap_or_default();
+            if let Some(llama) = local.iter().find(|l| l.name.eq(name)) {
+                println!("{}", serde_json::to_string_pretty(llama)?);
+                println!("Model file exists : {}", llama.model_exist(llama));
+            } else if let Some(ollama) = &setup.ollama {
+                let details = OllamaChat::show_model(ollama, name).await?;
+                println!("{}", serde_json::to_string_pretty(&details)?);
+            } else {
+                return Err(AirError::ModelLoad(format!(
+                    "Can't find local model name in setup : '{}'",
+                    name
+                )));
+            }
+        }
+        ModelsAction::Remove(name) => {
+            if !setup.remove_local(name) {
+                return Err(AirError::ModelLoad(format!(
+                    "Can't find local model name in setup : '{}'",
+                    name
+                )));
+            }
+            setup.save()?;
+            println!("Model {} removed from setup (the file is kept).", name);
+        }
+    }
+    Ok(())
+}
+
+/// `air config ...`, doesn't need a valid setup to edit it.
+pub fn config(action: &ConfigAction) -> Result<(), AirError> {
+    match action {
+        ConfigAction::Show => Setup::new()?.display(),
+        ConfigAction::Edit => {
+            let config = get_config_path("setup.json");
+            if !config.exists {
+                fs::write(&config.path, Setup::get_example()?)?;
+            }
+            let editor = std::env::var("VISUAL")
+                .or(std::env::var("EDITOR"))
+                .unwrap_or(DEFAULT_EDITOR.to_string());
+            let status = std::process::Command::new(&editor)
+                .arg(&config.path)
+                .status()?;
+            if !status.success() {
+                return Err(AirError::Config(format!(
+                    "{} exited with {}",
+                    editor, status
+                )));
+            }
+            Setup::new()?;
+            println!("Setup {} is valid.", config.path);
+        }
+        ConfigAction::Validate => {
+            Setup::new()?;
+            println!("Setup {} is valid.", get_config_path("setup.json").path);
+        }
+    }
+    Ok(())
+}
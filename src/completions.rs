@@ -0,0 +1,420 @@
+use crate::{
+    history::History,
+    options::{Complete, OptionSpec, ASK_OPTIONS, COMMANDS, COMPLETE_COMMAND},
+    setup::Setup,
+    templates,
+};
+
+// scripts generated from the option definitions of `CommandLine`, the names
+// (models, sessions ...) are asked to `air __complete <kind>` at completion
+// time so they follow the setup
+
+const BIN: &str = "air";
+
+/// `air completions <shell>`
+pub fn script(shell: &str) -> String {
+    return match shell {
+        "zsh" => zsh(),
+        "fish" => fish(),
+        _ => bash(),
+    };
+}
+
+/// `air __complete <kind>`, one name per line, nothing when the setup can't
+/// be read.
+pub fn names(kind: &str) -> Vec<String> {
+    let setup = match Setup::new() {
+        Ok(setup) => setup,
+        Err(e) => {
+            log::debug!("{}", e);
+            return vec![];
+        }
+    };
+    let names = match kind {
+        "models" => setup
+            .local
+            .iter()
+            .flatten()
+            .map(|l| l.name.clone())
+            .collect(),
+        "remotes" => setup
+            .remote
+            .iter()
+            .flatten()
+            .map(|r| r.name.clone())
+            .collect(),
+        "personas" => setup
+            .personas
+            .iter()
+            .flatten()
+            .map(|p| p.name.clone())
+            .collect(),
+        "sessions" => History::list().unwrap_or_default(),
+        "templates" => templates::get_template_names().unwrap_or_default(),
+        _ => vec![],
+    };
+    return names;
+}
+
+fn get_flags(spec: &OptionSpec) -> Vec<String> {
+    let mut flags: Vec<String> = vec![];
+    if !spec.short.is_empty() {
+        flags.push(format!("-{}", spec.short));
+    }
+    if !spec.long.is_empty() {
+        flags.push(format!("--{}", spec.long));
+    }
+    return flags;
+}
+
+fn bash_reply(complete: Complete) -> String {
+    return match complete {
+        Complete::Nothing => "COMPREPLY=()".to_string(),
+        Complete::Path => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+        Complete::Directory => "COMPREPLY=($(compgen -d -- \"$cur\"))".to_string(),
+        Complete::Values(values) => {
+            format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                values.join(" ")
+            )
+        }
+        Complete::Names(kind) => format!(
+            "COMPREPLY=($(compgen -W \"$({} {} {} 2>/dev/null)\" -- \"$cur\"))",
+            BIN, COMPLETE_COMMAND, kind
+        ),
+    };
+}
+
+/// `case "$prev"` entries completing the option values.
+fn bash_values(specs: &[OptionSpec], indent: &str) -> String {
+    let mut values = String::new();
+    for spec in specs.iter().filter(|s| !s.hint.is_empty()) {
+        values.push_str(&format!(
+            "{indent}{})\n{indent}    {}\n{indent}    return ;;\n",
+            get_flags(spec).join("|"),
+            bash_reply(spec.complete),
+            indent = indent
+        ));
+    }
+    return values;
+}
+
+fn bash() -> String {
+    let options: Vec<String> = ASK_OPTIONS.iter().flat_map(get_flags).collect();
+    let commands: Vec<&str> = COMMANDS.iter().map(|c| c.name).collect();
+    let values = bash_values(ASK_OPTIONS, "        ");
+
+    let mut subcommands = String::new();
+    for command in COMMANDS.iter().filter(|c| !c.actions.is_empty()) {
+        let actions: Vec<&str> = command.actions.iter().map(|a| a.0).collect();
+        subcommands.push_str(&format!("            {})\n", command.name));
+        if !command.options.is_empty() {
+            let flags: Vec<String> = command.options.iter().flat_map(get_flags).collect();
+            subcommands.push_str(&format!(
+                "                case \"$prev\" in\n{}                esac\n                \
+                 if [[ \"$cur\" == -* ]]; then\n                    \
+                 COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n                    \
+                 return\n                fi\n",
+                bash_values(command.options, "                    "),
+                flags.join(" ")
+            ));
+        }
+        subcommands.push_str(&format!(
+            "                if [ \"$COMP_CWORD\" -eq 2 ]; then\n                    \
+             COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+            actions.join(" ")
+        ));
+        let arguments: Vec<&(&str, Complete)> = command
+            .actions
+            .iter()
+            .filter(|a| a.1 != Complete::Nothing)
+            .collect();
+        if !arguments.is_empty() {
+            subcommands.push_str(
+                "                else\n                    case \"${COMP_WORDS[2]}\" in\n",
+            );
+            for (action, complete) in arguments {
+                subcommands.push_str(&format!(
+                    "                        {}) {} ;;\n",
+                    action,
+                    bash_reply(*complete)
+                ));
+            }
+            subcommands.push_str("                    esac\n");
+        }
+        subcommands.push_str("                fi\n                return ;;\n");
+    }
+
+    return format!(
+        r#"# bash completion for {bin}
+_{bin}() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+
+    if [ "$COMP_CWORD" -gt 1 ]; then
+        case "${{COMP_WORDS[1]}}" in
+{subcommands}        esac
+    fi
+
+    case "$prev" in
+{values}    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{options}" -- "$cur"))
+    elif [ "$COMP_CWORD" -eq 1 ]; then
+        COMPREPLY=($(compgen -W "{commands}" -- "$cur"))
+    fi
+}}
+complete -F _{bin} {bin}
+"#,
+        bin = BIN,
+        values = values,
+        subcommands = subcommands,
+        options = options.join(" "),
+        commands = commands.join(" ")
+    );
+}
+
+/// Escape a `_arguments` description.
+fn zsh_escape(text: &str) -> String {
+    return text
+        .replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:");
+}
+
+fn zsh_action(complete: Complete) -> String {
+    return match complete {
+        Complete::Nothing => " ".to_string(),
+        Complete::Path => "_files".to_string(),
+        Complete::Directory => "_files -/".to_string(),
+        Complete::Values(values) => format!("({})", values.join(" ")),
+        Complete::Names(kind) => format!("_{}_names {}", BIN, kind),
+    };
+}
+
+/// Options of a subcommand : their values, then the options themselves.
+fn zsh_options(specs: &[OptionSpec]) -> String {
+    let mut values = String::new();
+    for spec in specs.iter().filter(|s| !s.hint.is_empty()) {
+        let call = match spec.complete {
+            Complete::Values(values) => format!("compadd -- {}; ", values.join(" ")),
+            Complete::Nothing => String::new(),
+            complete => format!("{}; ", zsh_action(complete)),
+        };
+        values.push_str(&format!(
+            "                    {}) {}return ;;\n",
+            get_flags(spec).join("|"),
+            call
+        ));
+    }
+    let mut options = String::new();
+    for spec in specs {
+        for flag in get_flags(spec) {
+            options.push_str(&format!(" '{}:{}'", flag, zsh_escape(spec.description)));
+        }
+    }
+    return format!(
+        "                case $words[CURRENT-1] in\n{}                esac\n                \
+         if [[ $words[CURRENT] == -* ]]; then\n                    \
+         local -a options\n                    options=({})\n                    \
+         _describe 'option' options\n                    return\n                fi\n",
+        values,
+        options.trim_start()
+    );
+}
+
+fn zsh() -> String {
+    let mut arguments = String::new();
+    for spec in ASK_OPTIONS {
+        let flags = get_flags(spec);
+        let mut argument = String::new();
+        if spec.multi {
+            argument.push('*');
+        } else if flags.len() > 1 {
+            argument.push_str(&format!("({})", flags.join(" ")));
+        }
+        if flags.len() > 1 {
+            argument = format!("'{}'{{{}}}'", argument, flags.join(","));
+        } else {
+            argument = format!("'{}{}", argument, flags.join(""));
+        }
+        argument.push_str(&format!("[{}]", zsh_escape(spec.description)));
+        if !spec.hint.is_empty() {
+            argument.push_str(&format!(":{}:{}", spec.hint, zsh_action(spec.complete)));
+        }
+        arguments.push_str(&format!("        {}' \\\n", argument));
+    }
+
+    let mut commands = String::new();
+    for command in COMMANDS {
+        commands.push_str(&format!(
+            "        '{}:{}'\n",
+            command.name,
+            zsh_escape(command.description)
+        ));
+    }
+
+    let mut subcommands = String::new();
+    for command in COMMANDS.iter().filter(|c| !c.actions.is_empty()) {
+        let actions: Vec<&str> = command.actions.iter().map(|a| a.0).collect();
+        subcommands.push_str(&format!("            {})\n", command.name));
+        if !command.options.is_empty() {
+            subcommands.push_str(&zsh_options(command.options));
+        }
+        subcommands.push_str(&format!(
+            "                if (( CURRENT == 3 )); then\n                    \
+             compadd -- {}\n",
+            actions.join(" ")
+        ));
+        let arguments: Vec<&(&str, Complete)> = command
+            .actions
+            .iter()
+            .filter(|a| a.1 != Complete::Nothing)
+            .collect();
+        if !arguments.is_empty() {
+            subcommands.push_str("                else\n                    case $words[3] in\n");
+            for (action, complete) in arguments {
+                let call = match complete {
+                    Complete::Values(values) => format!("compadd -- {}", values.join(" ")),
+                    _ => zsh_action(*complete),
+                };
+                subcommands.push_str(&format!(
+                    "                        {}) {} ;;\n",
+                    action, call
+                ));
+            }
+            subcommands.push_str("                    esac\n");
+        }
+        subcommands.push_str("                fi\n                return ;;\n");
+    }
+
+    return format!(
+        r#"#compdef {bin}
+
+_{bin}_names() {{
+    local -a names
+    names=(${{(f)"$({bin} {complete} $1 2>/dev/null)"}})
+    compadd -a names
+}}
+
+_{bin}() {{
+    local -a commands
+    commands=(
+{commands}    )
+
+    if (( CURRENT > 2 )); then
+        case $words[2] in
+{subcommands}        esac
+    fi
+
+    if (( CURRENT == 2 )) && [[ $words[2] != -* ]]; then
+        _describe 'command' commands
+        return
+    fi
+    _arguments -s -S \
+{arguments}        '*::prompt:'
+}}
+
+_{bin} "$@"
+"#,
+        bin = BIN,
+        complete = COMPLETE_COMMAND,
+        commands = commands,
+        subcommands = subcommands,
+        arguments = arguments
+    );
+}
+
+/// Escape a single quoted fish string.
+fn fish_escape(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('\'', "\\'");
+}
+
+fn fish() -> String {
+    let names: Vec<&str> = COMMANDS.iter().map(|c| c.name).collect();
+    let mut lines: Vec<String> = vec![
+        format!("# fish completion for {}", BIN),
+        format!("complete -c {} -f", BIN),
+    ];
+
+    for command in COMMANDS {
+        lines.push(format!(
+            "complete -c {} -n '__fish_use_subcommand' -a {} -d '{}'",
+            BIN,
+            command.name,
+            fish_escape(command.description)
+        ));
+    }
+    for command in COMMANDS.iter().filter(|c| !c.actions.is_empty()) {
+        let actions: Vec<&str> = command.actions.iter().map(|a| a.0).collect();
+        for spec in command.options {
+            let condition = format!("__fish_seen_subcommand_from {}", command.name);
+            lines.push(fish_option(spec, &condition));
+        }
+        lines.push(format!(
+            "complete -c {} -n '__fish_seen_subcommand_from {}; and not __fish_seen_subcommand_from {}' -a '{}'",
+            BIN,
+            command.name,
+            actions.join(" "),
+            actions.join(" ")
+        ));
+        for (action, complete) in command.actions.iter().filter(|a| a.1 != Complete::Nothing) {
+            let condition = format!(
+                "__fish_seen_subcommand_from {}; and __fish_seen_subcommand_from {}",
+                command.name, action
+            );
+            let arguments = match complete {
+                Complete::Path => "-F".to_string(),
+                Complete::Directory => "-a '(__fish_complete_directories)'".to_string(),
+                Complete::Values(values) => format!("-a '{}'", values.join(" ")),
+                Complete::Names(kind) => format!("-a '({} {} {})'", BIN, COMPLETE_COMMAND, kind),
+                Complete::Nothing => String::new(),
+            };
+            lines.push(format!(
+                "complete -c {} -n '{}' {}",
+                BIN, condition, arguments
+            ));
+        }
+    }
+
+    // the ask options, also without the `ask` subcommand
+    let condition = format!(
+        "not __fish_seen_subcommand_from {}",
+        names
+            .iter()
+            .filter(|n| **n != "ask")
+            .cloned()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    );
+    for spec in ASK_OPTIONS {
+        lines.push(fish_option(spec, &condition));
+    }
+    return lines.join("\n") + "\n";
+}
+
+fn fish_option(spec: &OptionSpec, condition: &str) -> String {
+    let mut line = format!("complete -c {} -n '{}'", BIN, condition);
+    if !spec.short.is_empty() {
+        line.push_str(&format!(" -s {}", spec.short));
+    }
+    if !spec.long.is_empty() {
+        line.push_str(&format!(" -l {}", spec.long));
+    }
+    line.push_str(&format!(" -d '{}'", fish_escape(spec.description)));
+    if !spec.hint.is_empty() {
+        line.push_str(" -r");
+        match spec.complete {
+            Complete::Path | Complete::Directory => line.push_str(" -F"),
+            Complete::Values(values) => line.push_str(&format!(" -a '{}'", values.join(" "))),
+            Complete::Names(kind) => {
+                line.push_str(&format!(" -a '({} {} {})'", BIN, COMPLETE_COMMAND, kind))
+            }
+            Complete::Nothing => (),
+        }
+    }
+    return line;
+}
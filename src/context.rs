@@ -0,0 +1,173 @@
+use crate::{error::AirError, usage::estimate_tokens};
+use ignore::WalkBuilder;
+use std::fs;
+use std::path::Path;
+
+/// Bytes sniffed to tell text from binary files.
+const SNIFF: usize = 8 * 1024;
+
+/// Files attached to the prompt, packed as fenced blocks labelled by path.
+pub struct Context {
+    pub text: String,
+    pub files: Vec<String>,
+    pub dropped: Vec<(String, String)>,
+}
+
+impl Context {
+    /// Pack files and directories (walked with `.gitignore` rules) until the
+    /// budget (in bytes) is used, binaries and what does not fit are dropped.
+    pub fn pack(paths: &[String], budget: usize) -> Result<Self, AirError> {
+        let mut context = Context {
+            text: String::new(),
+            files: vec![],
+            dropped: vec![],
+        };
+        for path in paths {
+            if !Path::new(path).exists() {
+                return Err(AirError::Usage(format!("Can't find {}", path)));
+            }
+            for file in walk(path) {
+                context.add(&file, budget);
+            }
+        }
+        return Ok(context);
+    }
+
+    fn add(&mut self, path: &str, budget: usize) {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => return self.drop(path, &e.to_string()),
+        };
+        if is_binary(&bytes) {
+            return self.drop(path, "binary");
+        }
+        let content = String::from_utf8_lossy(&bytes);
+        let fence = get_fence(&content);
+        let block = format!(
+            "{}\n{}{}\n{}\n{}\n\n",
+            path,
+            fence,
+            get_language(path),
+            content.trim_end(),
+            fence
+        );
+        if self.text.len() + block.len() > budget {
+            return self.drop(path, &format!("over budget ({} bytes)", bytes.len()));
+        }
+        self.text.push_str(&block);
+        self.files.push(path.to_string());
+    }
+
+    fn drop(&mut self, path: &str, reason: &str) {
+        self.dropped.push((path.to_string(), reason.to_string()));
+    }
+
+    /// Warn about dropped files, with the packed size in verbose mode.
+    pub fn report(&self) {
+        for (path, reason) in &self.dropped {
+            log::warn!("Skipping {} : {}", path, reason);
+        }
+        log::info!(
+            "Context : {} files, {} bytes (~{} tokens)",
+            self.files.len(),
+            self.text.len(),
+            estimate_tokens(&self.text)
+        );
+    }
+
+    /// The prompt followed by the packed files.
+    pub fn to_prompt(&self, prompt: &str) -> String {
+        if self.text.is_empty() {
+            return prompt.to_string();
+        }
+        if prompt.is_empty() {
+            return self.text.trim_end().to_string();
+        }
+        return format!("{}\n\n{}", prompt, self.text.trim_end());
+    }
+}
+
+/// Files of a path, sorted, ignored files (`.gitignore`, hidden) excluded
+/// unless given explicitly.
+fn walk(path: &str) -> Vec<String> {
+    if Path::new(path).is_file() {
+        return vec![path.to_string()];
+    }
+    let mut files: Vec<String> = WalkBuilder::new(path)
+        .require_git(false)
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::warn!("{}", e);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    return files;
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    let sniff = &bytes[..bytes.len().min(SNIFF)];
+    if sniff.contains(&0) {
+        return true;
+    }
+    // a multibyte char may be cut at the end of the sniffed bytes
+    return match std::str::from_utf8(sniff) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some(),
+    };
+}
+
+/// A fence longer than any backtick run of the content.
+pub fn get_fence(content: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in content.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    return "`".repeat(longest.max(2) + 1);
+}
+
+fn get_language(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    return match extension.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "java" => "java",
+        "kt" => "kotlin",
+        "dart" => "dart",
+        "rb" => "ruby",
+        "php" => "php",
+        "cs" => "csharp",
+        "swift" => "swift",
+        "sh" | "bash" | "zsh" => "bash",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" => "xml",
+        "md" => "markdown",
+        _ => "",
+    };
+}
@@ -16,7 +16,7 @@ pub struct Displayer {
 impl Displayer {
     pub fn new(markdown: bool) -> Displayer {
         Displayer {
-            markdown: markdown,
+            markdown,
             buffer: String::new(),
             codeblock: false,
         }
@@ -29,14 +29,14 @@ impl Displayer {
             if line == "```" {
                 self.codeblock = false;
                 stdout.reset().unwrap();
-                write!(&mut stdout, "\n").unwrap();
+                writeln!(&mut stdout).unwrap();
             } else {
-                write!(&mut stdout, "{}\n", line).unwrap();
+                writeln!(&mut stdout, "{}", line).unwrap();
             }
             return;
         }
 
-        let parser: Parser<'_> = Parser::new(&line);
+        let parser: Parser<'_> = Parser::new(line);
         for event in parser {
             match event {
                 Event::Start(tag) => match tag {
@@ -77,7 +77,7 @@ impl Displayer {
                                     ColorSpec::new().set_bg(Some(Color::Rgb(0x2a, 0x2a, 0x2a))),
                                 )
                                 .unwrap();
-                            writeln!(&mut stdout, "").unwrap();
+                            writeln!(&mut stdout).unwrap();
                         } else {
                             stdout.reset().unwrap();
                         }
@@ -105,7 +105,7 @@ impl Displayer {
                 }
             }
         }
-        println!("");
+        println!();
     }
 
     pub fn display_markdown(&mut self, text: &str) {
@@ -1,23 +1,103 @@
+use reqwest::StatusCode;
 use std::{error::Error, fmt};
 
+/// Every error surfaced to the user, the process exits with `exit_code()`
+/// (see README "Exit codes").
 #[derive(Debug)]
-pub struct ErrorMessage {
-    message: String,
+pub enum AirError {
+    /// Unexpected I/O failure.
+    Io(std::io::Error),
+    /// Invalid command line or empty prompt.
+    Usage(String),
+    /// Missing or invalid setup file.
+    Config(String),
+    /// Missing, invalid or revoked api key.
+    Auth(String),
+    /// Server unreachable, timeout, broken stream...
+    Network(String),
+    /// Too many requests or quota exceeded.
+    RateLimit(String),
+    /// Model file missing, failing to load or unknown model name.
+    ModelLoad(String),
+    /// Prompt and history don't fit in the model context.
+    ContextOverflow(String),
+    /// Any other error returned by the backend.
+    Backend(String),
+    /// Command of the shell mode exited with an error.
+    CommandFailed(String),
+    /// Cancelled by the user (ctrl-c, declined confirmation).
+    UserAbort,
 }
 
-#[allow(dead_code)]
-impl ErrorMessage {
-    pub fn new(message: &str) -> ErrorMessage {
-        ErrorMessage {
-            message: message.to_string(),
+impl AirError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AirError::Io(_) => 1,
+            AirError::Usage(_) => 2,
+            AirError::Config(_) => 3,
+            AirError::Auth(_) => 4,
+            AirError::Network(_) => 5,
+            AirError::RateLimit(_) => 6,
+            AirError::ModelLoad(_) => 7,
+            AirError::ContextOverflow(_) => 8,
+            AirError::Backend(_) => 9,
+            AirError::CommandFailed(_) => 10,
+            AirError::UserAbort => 130,
+        }
+    }
+
+    /// Classify an http error status.
+    pub fn from_status(status: StatusCode, message: String) -> AirError {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AirError::Auth(message),
+            StatusCode::TOO_MANY_REQUESTS => AirError::RateLimit(message),
+            StatusCode::NOT_FOUND => AirError::ModelLoad(message),
+            StatusCode::PAYLOAD_TOO_LARGE => AirError::ContextOverflow(message),
+            _ => AirError::Backend(message),
         }
     }
 }
 
-impl fmt::Display for ErrorMessage {
+impl fmt::Display for AirError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self {
+            AirError::Io(e) => write!(f, "{}", e),
+            AirError::Usage(message) => write!(f, "{}", message),
+            AirError::Config(message) => write!(f, "Setup error : {}", message),
+            AirError::Auth(message) => write!(f, "Authentication error : {}", message),
+            AirError::Network(message) => write!(f, "Network error : {}", message),
+            AirError::RateLimit(message) => write!(f, "Rate limit : {}", message),
+            AirError::ModelLoad(message) => write!(f, "Model error : {}", message),
+            AirError::ContextOverflow(message) => write!(f, "Context overflow : {}", message),
+            AirError::Backend(message) => write!(f, "{}", message),
+            AirError::CommandFailed(message) => write!(f, "Command failed : {}", message),
+            AirError::UserAbort => write!(f, "Aborted."),
+        }
+    }
+}
+
+impl Error for AirError {}
+
+impl From<std::io::Error> for AirError {
+    fn from(e: std::io::Error) -> Self {
+        AirError::Io(e)
     }
 }
 
-impl Error for ErrorMessage {}
+impl From<serde_json::Error> for AirError {
+    fn from(e: serde_json::Error) -> Self {
+        AirError::Backend(format!("Invalid json : {}", e))
+    }
+}
+
+impl From<reqwest::Error> for AirError {
+    fn from(e: reqwest::Error) -> Self {
+        if let Some(status) = e.status() {
+            return AirError::from_status(status, e.to_string());
+        }
+        if e.is_decode() {
+            return AirError::Backend(e.to_string());
+        }
+        AirError::Network(e.to_string())
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    error::AirError,
+    history::{ArchivedMessage, HistoryMessage},
+    options::ExportFormat,
+};
+use pulldown_cmark::{html, Event, Options, Parser};
+use serde::Serialize;
+
+const HTML_STYLE: &str = "body { max-width: 52em; margin: 2em auto; padding: 0 1em; \
+    font-family: sans-serif; line-height: 1.5; color: #222; }
+h2 { font-size: 1em; color: #666; border-top: 1px solid #ddd; padding-top: 1em; }
+pre { background: #f5f5f5; padding: 0.8em; overflow-x: auto; border-radius: 4px; }
+code { font-family: monospace; background: #f5f5f5; }
+blockquote { color: #555; border-left: 3px solid #ddd; margin-left: 0; padding-left: 1em; }";
+
+/// OpenAI chat message.
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: String,
+}
+
+/// Transcript of `air history export`.
+pub fn render(
+    messages: &[ArchivedMessage],
+    format: ExportFormat,
+    title: &str,
+    system: Option<&str>,
+) -> Result<String, AirError> {
+    return match format {
+        ExportFormat::Markdown => Ok(to_markdown(messages, title, system)),
+        ExportFormat::Html => Ok(to_html(messages, title, system)),
+        ExportFormat::Json => to_json(messages, system),
+    };
+}
+
+/// Answer and the command run after it (shell mode).
+fn get_answer(message: &HistoryMessage) -> String {
+    let mut answer = message.assistant.clone();
+    if let Some(run) = &message.command {
+        let code = run
+            .exit_code
+            .map(|c| c.to_string())
+            .unwrap_or("none".to_string());
+        answer.push_str(&format!("\n\n> ran `{}`, exit code {}", run.command, code));
+    }
+    return answer;
+}
+
+fn to_markdown(messages: &[ArchivedMessage], title: &str, system: Option<&str>) -> String {
+    let mut markdown = format!("# {}\n\n", title);
+    if let Some(system) = system {
+        markdown.push_str(&format!("**System** : {}\n\n", system));
+    }
+    for archived in messages {
+        let message = &archived.message;
+        markdown.push_str(&format!(
+            "## {} · {}/{} · {}\n\n**User**\n\n{}\n\n**Assistant**\n\n{}\n\n",
+            message.date.format("%Y-%m-%d %H:%M"),
+            message.chat,
+            message.model.as_deref().unwrap_or("?"),
+            archived.session,
+            message.user.trim(),
+            get_answer(message).trim()
+        ));
+    }
+    return markdown;
+}
+
+fn escape_html(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+/// Standalone page, the markdown of the messages rendered (raw html of the
+/// messages is escaped).
+fn to_html(messages: &[ArchivedMessage], title: &str, system: Option<&str>) -> String {
+    let markdown = to_markdown(messages, title, system);
+    let parser = Parser::new_ext(
+        &markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+    .map(|event| match event {
+        Event::Html(text) | Event::InlineHtml(text) => Event::Text(text),
+        event => event,
+    });
+    let mut body = String::new();
+    html::push_html(&mut body, parser);
+    return format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n{}\n</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        HTML_STYLE,
+        body
+    );
+}
+
+/// OpenAI chat messages.
+fn to_json(messages: &[ArchivedMessage], system: Option<&str>) -> Result<String, AirError> {
+    let mut completions = vec![];
+    if let Some(system) = system {
+        completions.push(ChatMessage {
+            role: "system",
+            content: system.to_string(),
+        });
+    }
+    for archived in messages {
+        completions.push(ChatMessage {
+            role: "user",
+            content: archived.message.user.clone(),
+        });
+        completions.push(ChatMessage {
+            role: "assistant",
+            content: get_answer(&archived.message),
+        });
+    }
+    return Ok(serde_json::to_string_pretty(&completions)?);
+}
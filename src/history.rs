@@ -1,88 +1,779 @@
 use crate::{
-    ichat::{self, Role},
-    path::get_config_path,
+    error::AirError,
+    ichat::{self, Role, Usage},
+    path::{get_config_directory, get_config_path, is_valid_name},
+    store::{self, Lock},
 };
 use chrono::{Local, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, Debug)]
+pub const DEFAULT_SESSION: &str = "default";
+/// `history.jsonl`, the default session.
+const HISTORY_FILE: &str = "history";
+const SESSIONS_DIRECTORY: &str = "sessions";
+const CURRENT_SESSION: &str = "session.txt";
+const LOCK_FILE: &str = "history.lock";
+/// Messages dropped from the sessions (expired, cleared), kept for search.
+const ARCHIVE_FILE: &str = "archive.jsonl";
+/// Json files of the previous versions, migrated on first use.
+const LEGACY_EXTENSION: &str = "json";
+const EXTENSION: &str = "jsonl";
+/// First message of the completions, answered by the summary.
+const SUMMARY_REQUEST: &str = "Summarize our conversation so far.";
+/// Obsolete records (cleared, undone, expired) before the file is rewritten.
+const COMPACT_THRESHOLD: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HistoryMessage {
-    date: NaiveDateTime,
-    chat: String,
-    user: String,
-    assistant: String,
+    pub(crate) date: NaiveDateTime,
+    pub(crate) chat: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) model: Option<String>,
+    pub(crate) user: String,
+    pub(crate) assistant: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) command: Option<CommandRun>,
+}
+
+impl HistoryMessage {
+    /// Identifier given by `air history search` (date to the millisecond).
+    pub fn get_id(&self) -> String {
+        return self.date.format("%y%m%d-%H%M%S%3f").to_string();
+    }
+}
+
+/// A message and its session, as kept in the archive.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchivedMessage {
+    pub session: String,
+    #[serde(flatten)]
+    pub message: HistoryMessage,
+}
+
+/// Shell command of the answer which was run (shell mode).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommandRun {
+    pub(crate) command: String,
+    pub(crate) exit_code: Option<i32>,
 }
 
+/// What a named session remembers besides its messages.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SessionSetup {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    #[serde(default)]
+    pub anthropic: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// Older messages condensed by the model, replaces them in the completions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Summary {
+    pub(crate) text: String,
+    /// Identifiers of the messages summarized by this one.
+    pub(crate) ids: Vec<String>,
+    /// Messages summarized so far.
+    pub(crate) messages: usize,
+}
+
+/// Named session file of the previous versions.
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    #[serde(flatten)]
+    setup: SessionSetup,
+    messages: Vec<HistoryMessage>,
+}
+
+/// One line of a history file, the session is the replay of its records.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Message(HistoryMessage),
+    /// Command run after an answer (the last one in older files).
+    Command {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(flatten)]
+        run: CommandRun,
+    },
+    Setup(SessionSetup),
+    /// Summary replacing the messages it lists.
+    Summary(Summary),
+    /// Forget a message (the last one in older files).
+    Undo {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    Clear,
+}
+
+/// Messages of a session, the default session is the global `history.jsonl`
+/// (with expiration), named sessions are kept in `sessions/<name>.jsonl`.
+/// Changes are appended on `save()`, so concurrent `air` keep each other's
+/// messages.
 pub struct History {
+    session: String,
     file: String,
     exists: bool,
     expiration: u32,
     messages: Vec<HistoryMessage>,
+    summary: Option<Summary>,
+    /// Expired messages not summarized yet (default session).
+    expired: Vec<HistoryMessage>,
+    pub setup: SessionSetup,
+    /// Setup as written in the file.
+    saved: SessionSetup,
+    /// Records not written yet.
+    pending: Vec<Record>,
 }
 
 impl History {
-    pub fn new(expiration: u32) -> Self {
-        let config = get_config_path("history.json");
+    pub fn new(session: &str, expiration: u32) -> Self {
+        let config = get_config_path(&History::get_session_file(session, EXTENSION));
         return History {
+            session: session.to_string(),
             file: config.path,
             exists: config.exists,
             messages: vec![],
+            summary: None,
+            expired: vec![],
             expiration,
+            setup: SessionSetup::default(),
+            saved: SessionSetup::default(),
+            pending: vec![],
         };
     }
-    pub fn add(&mut self, chat: &str, user: &str, assistant: &str) {
+
+    /// Load an existing session.
+    pub fn open(session: &str, expiration: u32) -> Result<Self, AirError> {
+        if session != DEFAULT_SESSION {
+            History::check_name(session)?;
+        }
+        if !History::session_exists(session) {
+            return Err(AirError::Usage(format!("Can't find session '{}'", session)));
+        }
+        let mut history = History::new(session, expiration);
+        history.load()?;
+        return Ok(history);
+    }
+
+    pub fn get_session(&self) -> &str {
+        return &self.session;
+    }
+
+    pub fn get_file(&self) -> &str {
+        return &self.file;
+    }
+
+    pub fn add(
+        &mut self,
+        chat: &str,
+        model: &str,
+        user: &str,
+        assistant: &str,
+        usage: Option<Usage>,
+    ) {
         let local_time = Local::now();
         let naive_time: NaiveDateTime = local_time.naive_local();
 
-        self.messages.push(HistoryMessage {
+        let message = HistoryMessage {
             date: naive_time,
             chat: chat.to_string(),
+            model: Some(model.to_string()),
             user: user.to_string(),
             assistant: assistant.to_string(),
-        });
+            usage,
+            command: None,
+        };
+        self.messages.push(message.clone());
+        self.pending.push(Record::Message(message));
+    }
+
+    /// Record the command run after the last answer.
+    pub fn set_command(&mut self, command: &str, exit_code: Option<i32>) {
+        if let Some(message) = self.messages.last_mut() {
+            let run = CommandRun {
+                command: command.to_string(),
+                exit_code,
+            };
+            message.command = Some(run.clone());
+            self.pending.push(Record::Command {
+                id: Some(message.get_id()),
+                run,
+            });
+        }
     }
 
-    pub fn save(&self) -> Result<(), std::io::Error> {
-        let serialized = serde_json::to_string_pretty(&self.messages)?;
-        fs::write(&self.file, serialized.as_str())?;
+    /// Append the changes since the last load or save.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.session != DEFAULT_SESSION && self.setup != self.saved {
+            self.pending.push(Record::Setup(self.setup.clone()));
+        }
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let _lock = History::lock()?;
+        self.migrate()?;
+        if self.session != DEFAULT_SESSION {
+            History::create_sessions_directory()?;
+        }
+        let lines = History::to_lines(&self.pending)?;
+        store::append(Path::new(&self.file), &lines)?;
+        self.pending.clear();
+        self.saved = self.setup.clone();
+        self.exists = true;
         Ok(())
     }
 
+    pub fn len(&self) -> usize {
+        return self.messages.len();
+    }
+
+    pub fn get_messages(&self) -> &[HistoryMessage] {
+        return &self.messages;
+    }
+
+    pub fn to_markdown(&self) -> String {
+        return History::format_markdown(&self.get_completions());
+    }
+
+    /// Messages under `## User` and `## Assistant` titles.
+    pub fn format_markdown(messages: &[ichat::Message]) -> String {
+        let mut markdown = String::new();
+        for message in messages {
+            let title = if message.role == Role::User {
+                "User"
+            } else {
+                "Assistant"
+            };
+            markdown.push_str(&format!("## {}\n\n{}\n\n", title, message.content.text()));
+        }
+        return markdown;
+    }
+
+    /// The summary comes first, as the answer to a request for it.
     pub fn get_completions(&self) -> Vec<ichat::Message> {
         let mut completions = vec![];
-        for message in &self.messages {
-            completions.push(ichat::Message {
-                role: Role::User,
-                content: message.user.to_owned(),
-            });
-            completions.push(ichat::Message {
-                role: Role::Assistant,
-                content: message.assistant.to_owned(),
-            });
+        if let Some(summary) = &self.summary {
+            completions.push(ichat::Message::new(Role::User, SUMMARY_REQUEST.to_string()));
+            completions.push(ichat::Message::new(Role::Assistant, summary.text.clone()));
+        }
+        completions.extend(History::to_completions(&self.messages));
+        return completions;
+    }
+
+    /// User and assistant messages, the answer with the command run after it.
+    pub fn to_completions(messages: &[HistoryMessage]) -> Vec<ichat::Message> {
+        let mut completions = vec![];
+        for message in messages {
+            let mut assistant = message.assistant.to_owned();
+            if let Some(run) = &message.command {
+                let code = run
+                    .exit_code
+                    .map(|c| c.to_string())
+                    .unwrap_or("none".to_string());
+                assistant.push_str(&format!("\n\n(ran `{}`, exit code {})", run.command, code));
+            }
+            completions.push(ichat::Message::new(Role::User, message.user.to_owned()));
+            completions.push(ichat::Message::new(Role::Assistant, assistant));
         }
         return completions;
     }
 
+    pub fn get_summary(&self) -> Option<&Summary> {
+        return self.summary.as_ref();
+    }
+
+    /// The expired messages and the `count` oldest ones, to be summarized.
+    pub fn get_to_summarize(&self, count: usize) -> Vec<HistoryMessage> {
+        let mut messages = self.expired.clone();
+        messages.extend(self.messages.iter().take(count).cloned());
+        return messages;
+    }
+
+    /// Replace the summarized messages by the summary.
+    pub fn set_summary(&mut self, text: &str, summarized: &[HistoryMessage]) {
+        let previous = self.summary.as_ref().map(|s| s.messages).unwrap_or(0);
+        let summary = Summary {
+            text: text.to_string(),
+            ids: summarized.iter().map(|m| m.get_id()).collect(),
+            messages: previous + summarized.len(),
+        };
+        self.expired.clear();
+        self.messages.retain(|m| !summary.ids.contains(&m.get_id()));
+        self.summary = Some(summary.clone());
+        self.pending.push(Record::Summary(summary));
+    }
+
     pub fn is_expired(&self, date: NaiveDateTime) -> bool {
         let now: NaiveDateTime = Local::now().naive_local();
         let duration = now.signed_duration_since(date).num_seconds();
         return duration > self.expiration as i64;
     }
+    /// Forget the last exchange, returns its prompt.
+    pub fn remove_last(&mut self) -> Option<String> {
+        let message = self.messages.pop()?;
+        self.pending.push(Record::Undo {
+            id: Some(message.get_id()),
+        });
+        return Some(message.user);
+    }
+
     pub fn clear(&mut self) {
         self.messages.clear();
+        self.summary = None;
+        self.expired.clear();
+        self.pending.push(Record::Clear);
+    }
+
+    /// Replay the history file. Invalid records (a crash while writing) are
+    /// skipped and the file is backed up then rewritten without them.
+    pub fn load(&mut self) -> Result<(), Error> {
+        let _lock = History::lock()?;
+        self.migrate()?;
+        let path = PathBuf::from(&self.file);
+        if !path.exists() {
+            return Ok(());
+        }
+        self.exists = true;
+
+        let (records, invalid) = History::read_records(&path)?;
+        let written = History::get_written(&records);
+        let count = records.len() + invalid;
+        for record in records {
+            self.apply(record);
+        }
+        self.saved = self.setup.clone();
+        if self.session == DEFAULT_SESSION {
+            // named sessions don't expire
+            let messages = std::mem::take(&mut self.messages);
+            let (expired, messages) = messages.into_iter().partition(|m| self.is_expired(m.date));
+            self.messages = messages;
+            self.expired = expired;
+        }
+
+        if invalid > 0 {
+            let copy = store::backup(&path, "corrupt")?;
+            log::warn!(
+                "{} invalid record(s) in {}, backup saved to {}",
+                invalid,
+                self.file,
+                copy.display()
+            );
+            self.compact(written)?;
+        } else if count > self.get_records().len() + COMPACT_THRESHOLD {
+            self.compact(written)?;
+        }
+        Ok(())
+    }
+
+    /// Valid records of a history file and the number of invalid ones.
+    fn read_records(path: &Path) -> Result<(Vec<Record>, usize), Error> {
+        let contents = fs::read(path)?;
+        let mut records: Vec<Record> = vec![];
+        let mut invalid: usize = 0;
+        for line in String::from_utf8_lossy(&contents).lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Record>(line) {
+                Ok(record) => records.push(record),
+                Err(e) => {
+                    log::warn!("{} : invalid record skipped ({})", path.display(), e);
+                    invalid += 1;
+                }
+            }
+        }
+        return Ok((records, invalid));
+    }
+
+    /// Every message of the records, cleared and undone ones included.
+    fn get_written(records: &[Record]) -> Vec<HistoryMessage> {
+        let mut messages: Vec<HistoryMessage> = vec![];
+        for record in records {
+            match record {
+                Record::Message(message) => messages.push(message.clone()),
+                Record::Command { id, run } => {
+                    if let Some(message) = History::find_message(&mut messages, id) {
+                        message.command = Some(run.clone());
+                    }
+                }
+                _ => (),
+            }
+        }
+        return messages;
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Message(message) => self.messages.push(message),
+            Record::Command { id, run } => {
+                if let Some(message) = History::find_message(&mut self.messages, &id) {
+                    message.command = Some(run);
+                }
+            }
+            Record::Setup(setup) => self.setup = setup,
+            Record::Summary(summary) => {
+                self.messages.retain(|m| !summary.ids.contains(&m.get_id()));
+                self.summary = Some(summary);
+            }
+            Record::Undo { id: None } => {
+                self.messages.pop();
+            }
+            Record::Undo { id: Some(id) } => {
+                if let Some(index) = self.messages.iter().rposition(|m| m.get_id() == id) {
+                    self.messages.remove(index);
+                }
+            }
+            Record::Clear => {
+                self.messages.clear();
+                self.summary = None;
+            }
+        }
+    }
+
+    /// Message targeted by a record, the last one without identifier (the
+    /// records of older files).
+    fn find_message<'a>(
+        messages: &'a mut [HistoryMessage],
+        id: &Option<String>,
+    ) -> Option<&'a mut HistoryMessage> {
+        return match id {
+            Some(id) => messages.iter_mut().rev().find(|m| &m.get_id() == id),
+            None => messages.last_mut(),
+        };
+    }
+
+    /// Records of the current state.
+    fn get_records(&self) -> Vec<Record> {
+        let mut records = vec![];
+        if self.session != DEFAULT_SESSION && self.saved != SessionSetup::default() {
+            records.push(Record::Setup(self.saved.clone()));
+        }
+        if let Some(summary) = &self.summary {
+            records.push(Record::Summary(summary.clone()));
+        }
+        for message in &self.messages {
+            records.push(Record::Message(message.clone()));
+        }
+        return records;
+    }
+
+    /// Rewrite the file with the current state only (lock held), the other
+    /// written messages go to the archive.
+    fn compact(&self, written: Vec<HistoryMessage>) -> Result<(), Error> {
+        log::debug!("Compacting {}", self.file);
+        let kept: Vec<String> = self.messages.iter().map(|m| m.get_id()).collect();
+        let mut archived: Vec<String> = vec![];
+        for message in written.into_iter().filter(|m| !kept.contains(&m.get_id())) {
+            archived.push(serde_json::to_string(&ArchivedMessage {
+                session: self.session.clone(),
+                message,
+            })?);
+        }
+        if !archived.is_empty() {
+            store::append(
+                &Path::new(&get_config_directory()).join(ARCHIVE_FILE),
+                &archived,
+            )?;
+        }
+        let mut contents = History::to_lines(&self.get_records())?.join("\n");
+        contents.push('\n');
+        return store::write_atomic(Path::new(&self.file), &contents);
+    }
+
+    /// Convert the json file of the previous versions (lock held). An invalid
+    /// file is put aside and the session starts empty.
+    fn migrate(&self) -> Result<(), Error> {
+        let path = Path::new(&self.file);
+        let legacy = History::get_legacy_path(&self.session);
+        if path.exists() || !legacy.exists() {
+            return Ok(());
+        }
+        if self.session != DEFAULT_SESSION {
+            History::create_sessions_directory()?;
+        }
+        let contents = fs::read_to_string(&legacy)?;
+        let parsed = if self.session == DEFAULT_SESSION {
+            serde_json::from_str::<Vec<HistoryMessage>>(&contents)
+                .map(|messages| (SessionSetup::default(), messages))
+        } else {
+            serde_json::from_str::<SessionFile>(&contents)
+                .map(|session| (session.setup, session.messages))
+        };
+        match parsed {
+            Ok((setup, messages)) => {
+                let mut records = vec![];
+                if setup != SessionSetup::default() {
+                    records.push(Record::Setup(setup));
+                }
+                records.extend(messages.into_iter().map(Record::Message));
+                let mut contents = History::to_lines(&records)?.join("\n");
+                contents.push('\n');
+                store::write_atomic(path, &contents)?;
+                fs::rename(&legacy, store::with_suffix(&legacy, "bak"))?;
+                log::info!("{} migrated to {}", legacy.display(), self.file);
+            }
+            Err(e) => {
+                let copy = store::backup(&legacy, "corrupt")?;
+                fs::remove_file(&legacy)?;
+                log::warn!(
+                    "{} is invalid ({}), backup saved to {}",
+                    legacy.display(),
+                    e,
+                    copy.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Messages of all the sessions and of the archive, oldest first.
+    pub fn get_archive() -> Result<Vec<ArchivedMessage>, AirError> {
+        let _lock = History::lock()?;
+        let mut messages: Vec<ArchivedMessage> = vec![];
+        for session in History::list()? {
+            let history = History::new(&session, 0);
+            history.migrate()?;
+            let path = Path::new(&history.file);
+            if path.exists() {
+                let (records, _) = History::read_records(path)?;
+                for message in History::get_written(&records) {
+                    messages.push(ArchivedMessage {
+                        session: session.clone(),
+                        message,
+                    });
+                }
+            }
+        }
+        let archive = Path::new(&get_config_directory()).join(ARCHIVE_FILE);
+        if archive.exists() {
+            for line in String::from_utf8_lossy(&fs::read(&archive)?).lines() {
+                match serde_json::from_str::<ArchivedMessage>(line) {
+                    Ok(message) => messages.push(message),
+                    Err(e) => log::warn!("{} : invalid record skipped ({})", archive.display(), e),
+                }
+            }
+        }
+        // an interrupted compaction may have archived messages twice
+        messages.sort_by_key(|m| m.message.date);
+        messages
+            .dedup_by(|a, b| a.session == b.session && a.message.get_id() == b.message.get_id());
+        return Ok(messages);
+    }
+
+    /// Find a message by the identifier given by `air history search`, as
+    /// `<session>:<id>` when several sessions hold the same identifier.
+    pub fn find(id: &str) -> Result<ArchivedMessage, AirError> {
+        let (session, date) = match id.split_once(':') {
+            Some((session, date)) => (Some(session), date),
+            None => (None, id),
+        };
+        let mut found: Vec<ArchivedMessage> = History::get_archive()?
+            .into_iter()
+            .filter(|m| m.message.get_id() == date && session.is_none_or(|s| s == m.session))
+            .collect();
+        if found.len() > 1 {
+            let candidates: Vec<String> = found
+                .iter()
+                .map(|m| format!("{}:{}", m.session, date))
+                .collect();
+            return Err(AirError::Usage(format!(
+                "Message '{}' is in several sessions, use one of : {}",
+                id,
+                candidates.join(", ")
+            )));
+        }
+        return found
+            .pop()
+            .ok_or(AirError::Usage(format!("Can't find message '{}'", id)));
+    }
+
+    /// Add a copy of a past exchange as the context of the next prompt, dated
+    /// now so the original keeps its identifier.
+    pub fn reopen(&mut self, mut message: HistoryMessage) {
+        message.date = Local::now().naive_local();
+        self.messages.push(message.clone());
+        self.pending.push(Record::Message(message));
+    }
+
+    fn to_lines(records: &[Record]) -> Result<Vec<String>, serde_json::Error> {
+        return records.iter().map(serde_json::to_string).collect();
+    }
+
+    /// Every history file change is made with this lock held.
+    fn lock() -> Result<Lock, Error> {
+        return Lock::acquire(&Path::new(&get_config_directory()).join(LOCK_FILE));
+    }
+
+    fn get_session_file(name: &str, extension: &str) -> String {
+        if name == DEFAULT_SESSION {
+            return format!("{}.{}", HISTORY_FILE, extension);
+        }
+        return format!("{}/{}.{}", SESSIONS_DIRECTORY, name, extension);
+    }
+
+    fn get_legacy_path(name: &str) -> PathBuf {
+        return PathBuf::from(
+            get_config_path(&History::get_session_file(name, LEGACY_EXTENSION)).path,
+        );
+    }
+
+    fn create_sessions_directory() -> Result<(), Error> {
+        return fs::create_dir_all(Path::new(&get_config_directory()).join(SESSIONS_DIRECTORY));
+    }
+
+    fn check_name(name: &str) -> Result<(), AirError> {
+        if !is_valid_name(name) {
+            return Err(AirError::Usage(format!(
+                "Invalid session name '{}' (letters, digits, '-', '_' and '.')",
+                name
+            )));
+        }
+        Ok(())
     }
-    pub fn load(&mut self) -> Result<(), std::io::Error> {
-        if self.exists {
-            let contents = fs::read_to_string(&self.file)?;
-            let messages: Vec<HistoryMessage> = serde_json::from_str(&contents)?;
 
-            for message in messages {
-                if !self.is_expired(message.date) {
-                    self.messages.push(message);
+    fn session_exists(name: &str) -> bool {
+        return name == DEFAULT_SESSION
+            || get_config_path(&History::get_session_file(name, EXTENSION)).exists
+            || History::get_legacy_path(name).exists();
+    }
+
+    /// Session used when none is given on the command line.
+    pub fn get_current() -> String {
+        let config = get_config_path(CURRENT_SESSION);
+        if config.exists {
+            if let Ok(name) = fs::read_to_string(&config.path) {
+                let name = name.trim();
+                if History::session_exists(name) {
+                    return name.to_string();
                 }
             }
         }
+        return DEFAULT_SESSION.to_string();
+    }
+
+    pub fn set_current(name: &str) -> Result<(), AirError> {
+        History::check_name(name)?;
+        fs::write(get_config_path(CURRENT_SESSION).path, name)?;
+        Ok(())
+    }
+
+    /// Default session first, then the named sessions sorted.
+    pub fn list() -> Result<Vec<String>, AirError> {
+        History::create_sessions_directory()?;
+        let mut names: Vec<String> = vec![];
+        for entry in fs::read_dir(Path::new(&get_config_directory()).join(SESSIONS_DIRECTORY))? {
+            let path = entry?.path();
+            let extension = path.extension().unwrap_or_default();
+            if extension == EXTENSION || extension == LEGACY_EXTENSION {
+                if let Some(name) = path.file_stem() {
+                    names.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names.insert(0, DEFAULT_SESSION.to_string());
+        return Ok(names);
+    }
+
+    pub fn rename(name: &str, new_name: &str) -> Result<(), AirError> {
+        History::check_name(new_name)?;
+        if name == DEFAULT_SESSION || new_name == DEFAULT_SESSION {
+            return Err(AirError::Usage(format!(
+                "The {} session can't be renamed",
+                DEFAULT_SESSION
+            )));
+        }
+        if !History::session_exists(name) {
+            return Err(AirError::Usage(format!("Can't find session '{}'", name)));
+        }
+        if History::session_exists(new_name) {
+            return Err(AirError::Usage(format!(
+                "Session '{}' already exists",
+                new_name
+            )));
+        }
+        let _lock = History::lock()?;
+        for extension in [EXTENSION, LEGACY_EXTENSION] {
+            let file = get_config_path(&History::get_session_file(name, extension));
+            if file.exists {
+                fs::rename(
+                    file.path,
+                    get_config_path(&History::get_session_file(new_name, extension)).path,
+                )?;
+            }
+        }
+        History::rename_archived(name, new_name)?;
+        if History::get_current() == name {
+            History::set_current(new_name)?;
+        }
+        Ok(())
+    }
+
+    /// Archived messages follow their session (lock held), so search and
+    /// reopen still find them.
+    fn rename_archived(name: &str, new_name: &str) -> Result<(), Error> {
+        let archive = Path::new(&get_config_directory()).join(ARCHIVE_FILE);
+        if !archive.exists() {
+            return Ok(());
+        }
+        let mut renamed = false;
+        let mut lines: Vec<String> = vec![];
+        for line in String::from_utf8_lossy(&fs::read(&archive)?).lines() {
+            match serde_json::from_str::<ArchivedMessage>(line) {
+                Ok(mut archived) if archived.session == name => {
+                    archived.session = new_name.to_string();
+                    lines.push(serde_json::to_string(&archived)?);
+                    renamed = true;
+                }
+                // other sessions and invalid records are kept as is
+                _ => lines.push(line.to_string()),
+            }
+        }
+        if !renamed {
+            return Ok(());
+        }
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        return store::write_atomic(&archive, &contents);
+    }
+
+    pub fn delete(name: &str) -> Result<(), AirError> {
+        if name == DEFAULT_SESSION {
+            return Err(AirError::Usage(format!(
+                "The {} session can't be deleted (use 'air history clear')",
+                DEFAULT_SESSION
+            )));
+        }
+        if !History::session_exists(name) {
+            return Err(AirError::Usage(format!("Can't find session '{}'", name)));
+        }
+        let current = History::get_current();
+        let _lock = History::lock()?;
+        for extension in [EXTENSION, LEGACY_EXTENSION] {
+            let file = get_config_path(&History::get_session_file(name, extension));
+            if file.exists {
+                fs::remove_file(file.path)?;
+            }
+        }
+        if current == name {
+            History::set_current(DEFAULT_SESSION)?;
+        }
         Ok(())
     }
 }
@@ -1,7 +1,7 @@
+use crate::{error::AirError, output::Output, tools::ToolRegistry};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
-
 #[derive(PartialEq, Serialize, Deserialize, Debug, Clone)]
 pub enum Role {
     #[serde(rename = "user")]
@@ -10,6 +10,8 @@ pub enum Role {
     System,
     #[serde(rename = "assistant")]
     Assistant,
+    #[serde(rename = "tool")]
+    Tool,
 }
 
 #[allow(dead_code)]
@@ -19,29 +21,180 @@ impl Role {
             Role::User => "user",
             Role::System => "system",
             Role::Assistant => "assistant", // Notez que les deux derniers ont la même valeur de chaîne, tout comme dans l'exemple C#.
+            Role::Tool => "tool",
         }
     }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Plain text is serialized as a string (as in history files), content
+/// parts are only used when images are attached.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
 
-    pub fn to_string(&self) -> String {
-        self.as_str().to_string()
+impl Content {
+    /// Text without the images.
+    pub fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+/// Images are always sent inline as `data:<media type>;base64,<data>` urls.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+impl ImageUrl {
+    /// Media type and base64 data of a data url.
+    pub fn get_data(&self) -> Option<(&str, &str)> {
+        let data = self.url.strip_prefix("data:")?;
+        let (media_type, data) = data.split_once(";base64,")?;
+        return Some((media_type, data));
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub role: Role,
-    pub content: String,
+    pub content: Content,
+    /// Assistant asking for tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Tool result, id of the answered call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn new(role: Role, content: String) -> Self {
+        return Message {
+            role,
+            content: Content::Text(content),
+            tool_calls: None,
+            tool_call_id: None,
+        };
+    }
+
+    /// User prompt followed by the attached images.
+    pub fn with_images(role: Role, text: String, images: &[ContentPart]) -> Self {
+        if images.is_empty() {
+            return Message::new(role, text);
+        }
+        let mut parts = vec![ContentPart::Text { text }];
+        parts.extend_from_slice(images);
+        return Message {
+            content: Content::Parts(parts),
+            ..Message::new(role, String::new())
+        };
+    }
+
+    pub fn tool_result(id: String, content: String) -> Self {
+        return Message {
+            tool_call_id: Some(id),
+            ..Message::new(Role::Tool, content)
+        };
+    }
+}
+
+/// Tool call (openai format), `arguments` is a json string.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+/// Tool description sent to the model, `parameters` is a json schema.
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl Usage {
+    pub fn total(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// Sampling parameters, unset values are left to the backend defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct Sampling {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
 }
 
 #[async_trait]
 pub trait IChat {
     fn get_name(&mut self) -> &str;
+    fn get_model(&self) -> String;
+    /// Token usage of the last `chat` call.
+    fn get_usage(&self) -> Option<Usage>;
+    /// Why the last `chat` call stopped (stop, length, tool_calls ...).
+    fn get_finish_reason(&self) -> Option<String>;
     fn set_system(&mut self, system: String);
     fn set_model(&mut self, model: String);
+    fn set_output(&mut self, output: Output);
+    fn set_sampling(&mut self, sampling: Sampling);
+    /// Enable tool calling, ignored by backends without support.
+    fn set_tools(&mut self, _tools: ToolRegistry) {
+        log::warn!("Tools are not supported by this backend");
+    }
+    /// Images attached to the next prompt, ignored by text only backends.
+    fn set_images(&mut self, _images: Vec<ContentPart>) {
+        log::warn!("Images are not supported by this backend");
+    }
     async fn chat(
         &mut self,
         prompt: String,
         history: Option<Vec<Message>>,
-    ) -> Result<String, Box<dyn std::error::Error>>;
+    ) -> Result<String, AirError>;
 }
-
@@ -0,0 +1,51 @@
+use crate::{
+    error::AirError,
+    ichat::{ContentPart, ImageUrl},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::fs;
+
+/// Largest image accepted by the apis (anthropic : 5MB).
+const MAX_IMAGE_SIZE: usize = 5 * 1024 * 1024;
+
+/// Guess the media type from the first bytes of the file.
+fn get_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some("image/jpeg");
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if bytes.len() > 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    return None;
+}
+
+/// Load an image file as a base64 data url content part.
+pub fn load_image(path: &str) -> Result<ContentPart, AirError> {
+    let bytes = fs::read(path)
+        .map_err(|e| AirError::Usage(format!("Can't read image {} : {}", path, e)))?;
+    if bytes.len() > MAX_IMAGE_SIZE {
+        return Err(AirError::Usage(format!(
+            "Image {} is too large ({} bytes, max {})",
+            path,
+            bytes.len(),
+            MAX_IMAGE_SIZE
+        )));
+    }
+    let media_type = get_media_type(&bytes).ok_or(AirError::Usage(format!(
+        "Unsupported image format (png, jpeg, gif or webp) : {}",
+        path
+    )))?;
+    log::debug!("Image {} : {} ({} bytes)", path, media_type, bytes.len());
+
+    return Ok(ContentPart::ImageUrl {
+        image_url: ImageUrl {
+            url: format!("data:{};base64,{}", media_type, STANDARD.encode(&bytes)),
+        },
+    });
+}
@@ -1,8 +1,14 @@
-use std::io::Write;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
 
 use crate::{
-    ichat::{IChat, Message},
+    error::AirError,
+    ichat::{IChat, Message, Sampling, Usage},
+    output::Output,
     setup::LLamaSetup,
+    usage::estimate_tokens,
 };
 use async_trait::async_trait;
 use libc::c_char;
@@ -13,10 +19,21 @@ use llama_cpp_rs::{
 
 // https://github.com/mdrokz/rust-llama.cpp
 
+/// Stops the generation when the answer is dropped (ctrl-c in the repl).
+struct Cancel(Arc<AtomicBool>);
+
+impl Drop for Cancel {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
 pub struct LLamaChat {
     pub setup: LLamaSetup,
     pub system: Option<String>,
     pub verbose: bool,
+    pub usage: Option<Usage>,
+    pub output: Output,
 }
 
 #[async_trait]
@@ -33,15 +50,47 @@ impl IChat for LLamaChat {
         // none
     }
 
+    fn get_model(&self) -> String {
+        return self.setup.name.clone();
+    }
+
+    fn get_usage(&self) -> Option<Usage> {
+        return self.usage;
+    }
+
+    fn get_finish_reason(&self) -> Option<String> {
+        // not exposed by the binding
+        return None;
+    }
+
+    fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) {
+        self.setup.temperature = sampling.temperature.or(self.setup.temperature);
+        self.setup.top_p = sampling.top_p.or(self.setup.top_p);
+    }
+
     async fn chat(
         &mut self,
         prompt: String,
         history: Option<Vec<Message>>,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let model_options = ModelOptions {
+    ) -> Result<String, AirError> {
+        let mut model_options = ModelOptions {
             n_gpu_layers: self.setup.n_gpu_layers.unwrap_or(0),
             ..Default::default()
         };
+        if let Some(context_size) = self.setup.context_size {
+            model_options.context_size = context_size;
+        }
+
+        if !self.setup.model_exist(&self.setup) {
+            return Err(AirError::ModelLoad(format!(
+                "Model file not found : {}",
+                self.setup.model
+            )));
+        }
 
         self.close_stderr();
         let llama: LLama = match LLama::new(self.setup.model.clone(), &model_options) {
@@ -51,10 +100,17 @@ impl IChat for LLamaChat {
             }
             Err(e) => {
                 self.open_stderr();
-                return Err(e);
+                return Err(AirError::ModelLoad(e.to_string()));
             }
         };
 
+        // one callback per generated token
+        let generated = Arc::new(AtomicU32::new(0));
+        let counter = generated.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let stop = cancelled.clone();
+        let output = self.output;
+
         let def: PredictOptions = PredictOptions::default();
         let predict_options: PredictOptions = PredictOptions {
             tokens: self.setup.tokens.unwrap_or(def.tokens),
@@ -62,10 +118,13 @@ impl IChat for LLamaChat {
             top_k: self.setup.top_k.unwrap_or(def.top_k),
             top_p: self.setup.top_p.unwrap_or(def.top_p),
             temperature: self.setup.temperature.unwrap_or(def.temperature),
-            token_callback: Some(Box::new(|token: String| {
+            token_callback: Some(Box::new(move |token: String| {
+                if stop.load(Ordering::Relaxed) {
+                    return false;
+                }
                 // TODO: Find a way to call self.display() here
-                print!("{}", token);
-                std::io::stdout().flush().unwrap();
+                counter.fetch_add(1, Ordering::Relaxed);
+                output.token(&token);
                 true
             })),
             ..Default::default()
@@ -81,8 +140,23 @@ impl IChat for LLamaChat {
         log::info!("tokens       : {}", predict_options.tokens);
         log::info!("prompt       : {}", pfmt);
 
-        let answer = llama.predict(pfmt, predict_options)?;
-        println!("");
+        // no tokenizer exposed by the binding, the prompt is estimated
+        let prompt_tokens = estimate_tokens(&pfmt);
+        // predict blocks, it runs aside so the caller can still be cancelled
+        let _cancel = Cancel(cancelled);
+        let answer = tokio::task::spawn_blocking(move || {
+            llama
+                .predict(pfmt, predict_options)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| AirError::Backend(e.to_string()))?
+        .map_err(AirError::Backend)?;
+        self.usage = Some(Usage {
+            prompt_tokens,
+            completion_tokens: generated.load(Ordering::Relaxed),
+        });
+        self.output.end();
 
         return Ok(answer);
     }
@@ -139,7 +213,7 @@ impl LLamaChat {
             .clone()
             .unwrap()
             .replace("{system}", &sys)
-            .replace("{prompt}", &prompt)
+            .replace("{prompt}", prompt)
             .to_string();
     }
 
@@ -148,6 +222,8 @@ impl LLamaChat {
             setup: setup.clone(),
             system: None,
             verbose,
+            usage: None,
+            output: Output::Text,
         };
     }
 
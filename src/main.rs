@@ -1,64 +1,237 @@
+// explicit `return` is the style of the code base
+#![allow(clippy::needless_return)]
+
+mod anthropic;
+mod commands;
+mod completions;
+mod context;
+mod displayer;
 mod error;
+mod export;
 mod history;
 mod ichat;
+mod image;
 mod llama;
+mod ollama;
 mod openai;
 mod options;
+mod output;
 mod path;
+mod repl;
+mod retry;
 mod scan;
 mod setup;
-mod displayer;
+mod shell;
+mod sse;
+mod stdin;
+mod store;
+mod summary;
+mod templates;
+mod tools;
+mod usage;
+mod window;
 
-use crate::history::History;
+use crate::anthropic::Anthropic;
+use crate::context::Context;
+use crate::error::AirError;
+use crate::history::{History, DEFAULT_SESSION};
 use crate::ichat::IChat;
 use crate::llama::LLamaChat;
-use crate::options::CommandLine;
+use crate::options::{Command, CommandLine, TemplatesAction};
+use crate::output::JsonAnswer;
+use crate::retry::Retry;
+use crate::templates::Template;
+use crate::tools::ToolRegistry;
+use ollama::OllamaChat;
 use openai::OpenAI;
-use scan::scan_folder;
 use setup::{LLamaSetup, Setup};
 use std::io::Write;
+use std::time::Instant;
 use termimad::{crossterm::style::Stylize, *};
 
 #[allow(dead_code)]
 fn display(markdown: bool, content: String) {
     if markdown {
         let skin = MadSkin::default();
-        println!("{}", skin.term_text(&content.trim()));
+        println!("{}", skin.term_text(content.trim()));
     } else {
         println!("{}", content.trim());
     }
 }
 
 fn get_local<'a>(local: &'a [LLamaSetup], name: &str) -> Option<&'a LLamaSetup> {
-    for l in local {
-        if l.name.eq(name) {
-            return Some(l);
-        }
-    }
-    return None;
+    return local.iter().find(|l| l.name.eq(name));
 }
 
 fn get_chat(
     local: &Option<String>,
     setup: &Setup,
     options: &CommandLine,
-) -> Result<Box<dyn IChat>, String> {
+) -> Result<Box<dyn IChat>, AirError> {
+    let retry = Retry::new(&setup.get_retry());
     if let Some(local) = local {
-        if let Some(locals) = &setup.local {
-            let _name: String = local.clone();
-            let llama: Option<&LLamaSetup> = get_local(&locals, &_name);
+        let llama: Option<&LLamaSetup> = match &setup.local {
+            Some(locals) => get_local(locals, local),
+            None => None,
+        };
 
-            if let Some(llama) = llama {
-                return Ok(Box::new(LLamaChat::new(llama, options.verbose)));
-            } else {
-                return Err(format!(
-                    "Can't find local model name in setup : '{}'",
-                    _name
-                ));
-            }
+        if let Some(llama) = llama {
+            return Ok(Box::new(LLamaChat::new(llama, options.verbose)));
+        }
+        // not a gguf file, let ollama resolve the model name
+        if let Some(ollama) = &setup.ollama {
+            return Ok(Box::new(OllamaChat::new(ollama, local, retry)));
+        }
+        return Err(AirError::ModelLoad(format!(
+            "Can't find local model name in setup : '{}'",
+            local
+        )));
+    }
+    if options.anthropic {
+        if let Some(anthropic) = &setup.anthropic {
+            let mut claude = Anthropic::new(
+                anthropic.apikey.clone(),
+                anthropic.get_base_url(),
+                anthropic.get_max_tokens(),
+                retry,
+            );
+            claude.set_model(anthropic.get_model());
+            return Ok(Box::new(claude));
+        }
+        return Err(AirError::Config(
+            "Can't find anthropic section in setup".to_string(),
+        ));
+    }
+    if let Some(remote) = &options.remote {
+        if let Some(remote) = setup.get_remote(remote) {
+            let mut openai = OpenAI::new(
+                remote.apikey.clone().unwrap_or_default(),
+                remote.base_url.clone(),
+                remote.headers.clone().unwrap_or_default(),
+                retry,
+            );
+            openai.set_model(remote.model.clone().unwrap_or(setup.get_model()));
+            openai.set_stream_usage(remote.get_stream_usage());
+            return Ok(Box::new(openai));
+        }
+        return Err(AirError::Config(format!(
+            "Can't find remote profile name in setup : '{}'",
+            remote
+        )));
+    }
+    setup.check_apikey()?;
+    let mut openai = OpenAI::new(
+        setup.get_apikey(),
+        setup.get_base_url(),
+        setup.get_headers(),
+        retry,
+    );
+    openai.set_model(setup.get_model());
+    openai.set_stream_usage(setup.get_stream_usage());
+    return Ok(Box::new(openai));
+}
+
+fn fail(error: AirError) -> ! {
+    log::error!("{}", error);
+    std::process::exit(error.exit_code());
+}
+
+/// Send the prompt with the history as context, record the usage and the answer.
+async fn ask(
+    ichat: &mut Box<dyn IChat>,
+    setup: &Setup,
+    options: &CommandLine,
+    history: &mut History,
+    system: &str,
+    prompt: &str,
+) -> Result<String, AirError> {
+    // history cut to the context window of the model
+    let name = ichat.get_name().to_string();
+    let model = ichat.get_model();
+    let (completions, trim) = window::fit(
+        history.get_completions(),
+        system,
+        prompt,
+        setup.get_context_window(&name, &model),
+        setup.get_reserved_tokens(&name),
+    );
+    trim.report();
+
+    let start = Instant::now();
+    let answer: String = ichat.chat(prompt.to_string(), Some(completions)).await?;
+    let duration = start.elapsed();
+
+    // if ichat.get_name() != "llama" {
+    //     display(options.markdown, answer.clone());
+    // }
+
+    if answer.trim().is_empty() {
+        return Err(AirError::Backend("Empty answer.".to_string()));
+    }
+
+    let usage = ichat.get_usage();
+    let cost = usage.and_then(|usage| usage::get_cost(&usage, setup.get_price(&model)));
+    if let Some(usage) = &usage {
+        if options.show_usage && !options.output.is_json() {
+            usage::display(usage, cost);
+        }
+        if let Err(e) = usage::record(&name, &model, usage, cost) {
+            log::error!("{}", e);
         }
     }
-    return Ok(Box::new(OpenAI::new(setup.apikey.clone())));
+    options.output.answer(JsonAnswer {
+        kind: None,
+        backend: &name,
+        model: &model,
+        system: Some(system).filter(|s| !s.trim().is_empty()),
+        answer: &answer,
+        finish_reason: ichat.get_finish_reason(),
+        duration_ms: duration.as_millis(),
+        usage,
+        cost,
+    })?;
+
+    history.add(&name, &model, prompt, &answer, usage);
+    history.setup.model = Some(model);
+    match history.save() {
+        Ok(_) => {
+            log::info!("History saved.")
+        }
+        Err(e) => {
+            log::error!("{}", e);
+        }
+    }
+    return Ok(answer);
+}
+
+/// A named session keeps its backend, model, persona and system prompt,
+/// unless given on the command line.
+fn use_session(history: &mut History, options: &mut CommandLine) {
+    if history.get_session() == DEFAULT_SESSION {
+        return;
+    }
+    let session = &mut history.setup;
+    if options.local.is_none() && options.remote.is_none() && !options.anthropic {
+        options.local = session.local.clone();
+        options.remote = session.remote.clone();
+        options.anthropic = session.anthropic;
+    } else {
+        session.local = options.local.clone();
+        session.remote = options.remote.clone();
+        session.anthropic = options.anthropic;
+        session.model = None;
+    }
+    if options.persona.is_some() {
+        session.persona = options.persona.clone();
+        session.system = None;
+        session.model = None;
+    } else {
+        options.persona = session.persona.clone();
+    }
+    match &options.system {
+        Some(system) => session.system = Some(system.clone()),
+        None => options.system = session.system.clone(),
+    }
 }
 
 fn init_log(verbose: bool) {
@@ -90,118 +263,207 @@ async fn main() -> Result<(), std::io::Error> {
     // let mut displayer = Displayer::new();
     // displayer.display("input");
 
+    let mut options = match CommandLine::new() {
+        Ok(options) => options,
+        Err(usage) => {
+            println!("{}", usage);
+            std::process::exit(AirError::Usage(usage).exit_code());
+        }
+    };
+    init_log(options.verbose);
+    match &options.command {
+        Command::Completions(shell) => {
+            print!("{}", completions::script(shell));
+            std::process::exit(0);
+        }
+        Command::Complete(kind) => {
+            for name in completions::names(kind) {
+                println!("{}", name);
+            }
+            std::process::exit(0);
+        }
+        _ => (),
+    }
+    if let Command::Config(action) = &options.command {
+        match commands::config(action) {
+            Ok(_) => std::process::exit(0),
+            Err(e) => fail(e),
+        }
+    }
+
     let mut setup: Setup = match Setup::new() {
         Ok(setup) => setup,
         Err(e) => {
             println!("Error: {}", e);
-            std::process::exit(10);
+            std::process::exit(e.exit_code());
         }
     };
-    let options = match CommandLine::new(&setup.get_system(), setup.get_markdown()) {
-        Ok(options) => options,
-        Err(usage) => {
-            println!("{}", usage);
-            std::process::exit(10);
+    options.apply_setup(&setup);
+
+    let done = match &options.command {
+        Command::Ask => Ok(false),
+        Command::Usage => usage::report().map(|_| true).map_err(AirError::from),
+        Command::History(action) => commands::history(action, &setup).map(|_| true),
+        Command::Models(action) => commands::models(action, &mut setup).await.map(|_| true),
+        Command::Templates(TemplatesAction::List) => templates::list().map(|_| true),
+        Command::Config(_) | Command::Completions(_) | Command::Complete(_) => Ok(true),
+    };
+    match done {
+        Ok(true) => std::process::exit(0),
+        Ok(false) => (),
+        Err(e) => fail(e),
+    }
+    // stdin is waited for when it's the prompt or a template variable
+    let requested = (options.prompt.is_empty() && options.template.is_none())
+        || options.stdin_prompt
+        || templates::reads_stdin(&options.vars);
+    let mut piped = match stdin::read_stdin(setup.get_max_stdin(), requested) {
+        Ok(piped) => piped,
+        Err(e) => fail(e),
+    };
+    if let Some(name) = &options.template {
+        // a variable may take the piped input
+        match Template::load(name).and_then(|t| t.render(&options.vars, &mut piped)) {
+            Ok(rendered) if options.prompt.is_empty() => options.prompt = rendered,
+            Ok(rendered) => options.prompt = format!("{}\n\n{}", rendered, options.prompt),
+            Err(e) => fail(e),
         }
+    }
+    if let Some(input) = &piped {
+        options.prompt = stdin::combine(&options.prompt, input, options.stdin_prompt);
+    }
+    if !options.files.is_empty() {
+        let context = match Context::pack(&options.files, setup.get_max_context()) {
+            Ok(context) => context,
+            Err(e) => fail(e),
+        };
+        context.report();
+        options.prompt = context.to_prompt(&options.prompt);
+    }
+
+    let session = match &options.session {
+        Some(session) => match History::set_current(session) {
+            Ok(_) => session.clone(),
+            Err(e) => fail(e),
+        },
+        None => History::get_current(),
     };
-    init_log(options.verbose);
-    if let Some(scan) = options.scan {
-        scan_folder(&mut setup, &scan);
-        std::process::exit(0);
+    let mut history = History::new(&session, setup.get_expiration());
+    if let Err(e) = history.load() {
+        log::error!("{}", e);
+    }
+    use_session(&mut history, &mut options);
+    if let Some(id) = &options.reopen {
+        match History::find(id) {
+            Ok(found) => history.reopen(found.message),
+            Err(e) => fail(e),
+        }
+    }
+
+    // system prompt : -s, then the session, the persona and the setup
+    let persona = match &options.persona {
+        Some(name) => match setup.get_persona(name) {
+            Some(persona) => Some(persona.clone()),
+            None => fail(AirError::Config(format!(
+                "Can't find persona name in setup : '{}'",
+                name
+            ))),
+        },
+        None => None,
+    };
+    if options.system.is_none() {
+        options.system = match &persona {
+            Some(persona) => Some(persona.system.clone()),
+            None => Some(setup.get_system()),
+        };
     }
 
-    let mut history = History::new(setup.get_expiration());
     let mut ichat = match get_chat(&options.local, &setup, &options) {
         Ok(chat) => chat,
-        Err(message) => {
-            log::error!("{}", message);
-            std::process::exit(5);
-        }
+        Err(e) => fail(e),
     };
+    if let Some(persona) = &persona {
+        if let Some(model) = &persona.model {
+            ichat.set_model(model.clone());
+        }
+        ichat.set_sampling(persona.get_sampling());
+    }
+    if let Some(model) = &history.setup.model {
+        ichat.set_model(model.clone());
+    }
+    // older messages condensed rather than dropped
+    if setup.get_summarize() {
+        if let Err(e) = summary::update(&mut ichat, &setup, &options, &mut history).await {
+            log::error!("Summary failed, older messages are dropped : {}", e);
+        }
+    }
+    ichat.set_output(options.output);
 
     if options.verbose {
         log::info!("Setup Path : {}", path::get_config_directory());
         log::info!("Setup      : {:?}", path::get_config_path("setup.json"));
-        log::info!("History    : {:?}", path::get_config_path("history.json"));
+        log::info!("Session    : {}", history.get_session());
+        log::info!("History    : {}", history.get_file());
         log::info!("___");
 
         setup.display();
         options.display();
     }
 
-    if options.list {
-        println!("Available models:");
-        for llm in &setup.local.unwrap_or(Vec::new()) {
-            let exists = if llm.model_exist(&llm) {
-                Stylize::green("✓").to_string()
-            } else {
-                Stylize::red("✗").to_string()
-            };
-            println!("  - {} {}", llm.name, exists);
+    if options.tools {
+        ichat.set_tools(ToolRegistry::new());
+    }
+
+    if !options.images.is_empty() {
+        let mut images = vec![];
+        for path in &options.images {
+            match image::load_image(path) {
+                Ok(image) => images.push(image),
+                Err(e) => fail(e),
+            }
         }
-        std::process::exit(0);
+        ichat.set_images(images);
     }
 
-    ichat.set_model(setup.get_model());
-    if let Some(system) = options.system {
+    if let Some(system) = &options.system {
         if !system.trim().is_empty() {
-            ichat.set_system(system);
+            ichat.set_system(system.clone());
         }
     }
 
-    match history.load() {
-        Ok(_) => {
-            if options.clear {
-                history.clear();
-                match history.save() {
-                    Ok(_) => {
-                        println!("History cleared.");
-                    }
-                    Err(e) => {
-                        log::error!("{}", e);
-                    }
-                }
-            }
+    if options.prompt.is_empty() {
+        if !stdin::is_terminal() {
+            fail(AirError::Usage("No prompt.".to_string()));
         }
-        Err(e) => {
-            log::error!("{}", e);
+        if options.shell {
+            fail(AirError::Usage("--shell needs a prompt.".to_string()));
+        }
+        if let Err(e) = repl::run(ichat, &mut history, &setup, &options).await {
+            fail(e);
         }
+        return Ok(());
     }
 
-    if options.prompt.is_empty() {
-        // don't display usage if --clear
-        if !options.clear {
-            eprintln!("{}", options.usage);
-            log::error!("Empty prompt.");
+    if options.shell {
+        if let Err(e) =
+            shell::run(&mut ichat, &setup, &options, &mut history, &options.prompt).await
+        {
+            fail(e);
         }
-        std::process::exit(5);
+        return Ok(());
     }
-
-    let answer: String = match ichat
-        .chat(options.prompt.to_string(), Some(history.get_completions()))
-        .await
+    let system = options.system.as_deref().unwrap_or("");
+    if let Err(e) = ask(
+        &mut ichat,
+        &setup,
+        &options,
+        &mut history,
+        system,
+        &options.prompt,
+    )
+    .await
     {
-        Ok(answer) => answer,
-        Err(message) => {
-            log::error!("{}", message);
-            std::process::exit(5);
-        }
-    };
-
-    // if ichat.get_name() != "llama" {
-    //     display(options.markdown, answer.clone());
-    // }
-
-    if answer.trim().len() > 0 {
-        history.add(ichat.get_name(), &*options.prompt, &*answer.clone());
-        match history.save() {
-            Ok(_) => {
-                log::info!("History saved.")
-            }
-            Err(e) => {
-                log::error!("{}", e);
-            }
-        }
+        fail(e);
     }
     Ok(())
 }
@@ -0,0 +1,337 @@
+use crate::{
+    error::AirError,
+    ichat::{IChat, Message, Role, Sampling, Usage},
+    output::Output,
+    retry::Retry,
+    setup::OllamaSetup,
+};
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+use tokio_stream::StreamExt;
+
+// https://github.com/ollama/ollama/blob/main/docs/api.md
+
+const HEADER_CONTENT_TYPE: &str = "Content-Type";
+const CONTENT_TYPE_JSON: &str = "application/json";
+const API_CHAT: &str = "/api/chat";
+const API_TAGS: &str = "/api/tags";
+const API_SHOW: &str = "/api/show";
+
+#[derive(Serialize, Debug, Default)]
+pub struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_thread: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_gpu: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OllamaCompletion<'a> {
+    model: &'a str,
+    messages: Vec<Message>,
+    stream: bool,
+    options: &'a OllamaOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<&'a str>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaMessage {
+    content: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct OllamaChunk {
+    model: Option<String>,
+    message: Option<OllamaMessage>,
+    done: Option<bool>,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaError {
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaModel {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+pub struct OllamaChat {
+    pub url: String,
+    pub model: String,
+    pub system: Option<String>,
+    pub options: OllamaOptions,
+    pub keep_alive: Option<String>,
+    pub retry: Retry,
+    pub usage: Option<Usage>,
+    pub output: Output,
+    pub finish_reason: Option<String>,
+}
+
+/// Handle one line of the NDJSON stream, a malformed line is logged and
+/// skipped so it doesn't abort the whole answer, an error line does.
+fn parse_line(
+    line: &str,
+    usage: &mut Option<Usage>,
+    finish_reason: &mut Option<String>,
+    output: Output,
+) -> Result<String, AirError> {
+    let mut message = String::new();
+    let json: OllamaChunk = match serde_json::from_str(line) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("Skipping malformed line ({}) : {}", e, line);
+            return Ok(message);
+        }
+    };
+
+    if let Some(error) = json.error {
+        return Err(AirError::Backend(error));
+    }
+    if let Some(msg) = json.message {
+        if let Some(content) = msg.content {
+            output.token(&content);
+            message.push_str(&content);
+        }
+    }
+    if json.done.unwrap_or(false) {
+        *usage = Some(Usage {
+            prompt_tokens: json.prompt_eval_count.unwrap_or(0),
+            completion_tokens: json.eval_count.unwrap_or(0),
+        });
+        *finish_reason = Some(json.done_reason.unwrap_or("stop".to_string()));
+        output.end();
+    }
+    Ok(message)
+}
+
+/// Decode the error of a non 2xx response.
+async fn get_error(response: Response) -> AirError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    log::debug!("{} : {}", status, body);
+
+    let message = match serde_json::from_str::<OllamaError>(&body) {
+        Ok(OllamaError { error: Some(error) }) => error,
+        _ => format!("{} {}", status, body.trim()),
+    };
+    return AirError::from_status(status, message);
+}
+
+#[async_trait]
+impl IChat for OllamaChat {
+    fn get_name(&mut self) -> &str {
+        return "ollama";
+    }
+
+    fn set_system(&mut self, system: String) {
+        self.system = Some(system)
+    }
+
+    fn set_model(&mut self, model: String) {
+        self.model = model
+    }
+
+    fn get_model(&self) -> String {
+        return self.model.clone();
+    }
+
+    fn get_usage(&self) -> Option<Usage> {
+        return self.usage;
+    }
+
+    fn get_finish_reason(&self) -> Option<String> {
+        return self.finish_reason.clone();
+    }
+
+    fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) {
+        self.options.temperature = sampling.temperature.or(self.options.temperature);
+        self.options.top_p = sampling.top_p.or(self.options.top_p);
+    }
+
+    async fn chat(
+        &mut self,
+        prompt: String,
+        history: Option<Vec<Message>>,
+    ) -> Result<String, AirError> {
+        let mut messages: Vec<Message> = vec![];
+
+        if let Some(sys) = &self.system {
+            if !sys.is_empty() {
+                messages.push(Message::new(Role::System, sys.to_string()));
+            }
+        }
+
+        if let Some(hs) = history {
+            for h in hs {
+                messages.push(h);
+            }
+        }
+
+        messages.push(Message::new(Role::User, prompt.to_string()));
+
+        let completion = OllamaCompletion {
+            model: &self.model,
+            stream: true,
+            options: &self.options,
+            keep_alive: self.keep_alive.as_deref(),
+            messages,
+        };
+        let serialized: String = serde_json::to_string_pretty(&completion)?;
+        log::debug!("{}\n", serialized);
+
+        let url = format!("{}{}", self.url, API_CHAT);
+        log::debug!("POST {}", url);
+
+        let client: Client = Client::new();
+        let request = client
+            .post(url)
+            .header(HEADER_CONTENT_TYPE, CONTENT_TYPE_JSON)
+            .body(serialized);
+        let response = self.retry.send(request).await?;
+        if !response.status().is_success() {
+            return Err(get_error(response).await);
+        }
+
+        // not SSE, one json object per line
+        let mut stream = response.bytes_stream();
+        let mut message: String = String::new();
+        let mut buffer: Vec<u8> = vec![];
+        self.usage = None;
+        self.finish_reason = None;
+
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(bytes) => {
+                    for c in bytes {
+                        if c == b'\n' {
+                            if !buffer.is_empty() {
+                                let text = parse_line(
+                                    &String::from_utf8_lossy(&buffer),
+                                    &mut self.usage,
+                                    &mut self.finish_reason,
+                                    self.output,
+                                )?;
+                                message.push_str(&text);
+                            }
+                            buffer = vec![];
+                        } else {
+                            buffer.push(c);
+                        }
+                    }
+                }
+                Err(e) => {
+                    // a truncated answer isn't an answer
+                    self.output.end();
+                    return Err(AirError::Network(format!("Stream interrupted : {}", e)));
+                }
+            }
+        }
+        // the last line may not end with a newline
+        if !buffer.is_empty() {
+            let text = parse_line(
+                &String::from_utf8_lossy(&buffer),
+                &mut self.usage,
+                &mut self.finish_reason,
+                self.output,
+            )?;
+            message.push_str(&text);
+        }
+        // set by the `done` line only
+        if self.finish_reason.is_none() {
+            self.output.end();
+            return Err(AirError::Backend(
+                "Stream ended before the answer was done.".to_string(),
+            ));
+        }
+        Ok(message)
+    }
+}
+
+impl OllamaChat {
+    pub fn new(setup: &OllamaSetup, model: &str, retry: Retry) -> Self {
+        return OllamaChat {
+            url: setup.get_url(),
+            model: model.to_string(),
+            system: None,
+            options: OllamaChat::get_options(setup),
+            keep_alive: setup.keep_alive.clone(),
+            retry,
+            usage: None,
+            output: Output::Text,
+            finish_reason: None,
+        };
+    }
+
+    /// Map llama-cpp style sampling options to ollama ones.
+    fn get_options(setup: &OllamaSetup) -> OllamaOptions {
+        return OllamaOptions {
+            temperature: setup.temperature,
+            top_k: setup.top_k,
+            top_p: setup.top_p,
+            // 0 means "until eos" for llama-cpp, -1 for ollama
+            num_predict: setup.tokens.map(|t| if t == 0 { -1 } else { t }),
+            num_thread: setup.threads,
+            num_gpu: setup.n_gpu_layers,
+            num_ctx: setup.context_size,
+        };
+    }
+
+    pub async fn list_models(setup: &OllamaSetup) -> Result<Vec<String>, AirError> {
+        let url = format!("{}{}", setup.get_url(), API_TAGS);
+        log::debug!("GET {}", url);
+
+        let response = Client::new().get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(get_error(response).await);
+        }
+        let tags: OllamaTags = response.json().await?;
+        return Ok(tags.models.into_iter().map(|m| m.name).collect());
+    }
+
+    /// Model details (format, family, parameters, quantization ...).
+    pub async fn show_model(
+        setup: &OllamaSetup,
+        name: &str,
+    ) -> Result<serde_json::Value, AirError> {
+        let url = format!("{}{}", setup.get_url(), API_SHOW);
+        log::debug!("POST {}", url);
+
+        let response = Client::new()
+            .post(url)
+            .json(&serde_json::json!({ "name": name }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(get_error(response).await);
+        }
+        let show: serde_json::Value = response.json().await?;
+        return Ok(show["details"].clone());
+    }
+}
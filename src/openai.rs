@@ -1,22 +1,52 @@
 use crate::{
-    ichat::{IChat, Message, Role},
+    error::AirError,
+    ichat::{ContentPart, IChat, Message, Role, Sampling, ToolCall, ToolDefinition, Usage},
+    output::Output,
+    retry::Retry,
+    sse::{SseDecoder, SseEvent},
+    tools::{ToolRegistry, MAX_ROUNDS},
 };
 use async_trait::async_trait;
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use std::collections::HashMap;
+use std::fmt;
 use tokio_stream::StreamExt;
 
 const HEADER_AUTHORIZATION: &str = "Authorization";
 const HEADER_CONTENT_TYPE: &str = "Content-Type";
 const CONTENT_TYPE_JSON: &str = "application/json";
-const OPENAI_URL: &str = "https://api.openai.com/v1/chat/completions";
+const CHAT_COMPLETIONS: &str = "/chat/completions";
 
 #[derive(Serialize, Debug)]
 pub struct OpenAICompletion<'a> {
     model: &'a str,
-    messages: Vec<Message>,
+    messages: &'a Vec<Message>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(flatten)]
+    sampling: Sampling,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAITool<'a>>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OpenAITool<'a> {
+    #[serde(rename = "type")]
+    tool_type: &'a str,
+    function: ToolDefinition,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpenAIUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -28,13 +58,59 @@ pub struct OpenAIChoice {
 }
 
 #[derive(Deserialize, Debug)]
-#[allow(dead_code)]
 pub struct OpenAIError {
     message: Option<String>,
     #[serde(rename = "type")]
     error_type: Option<String>,
-    param: Option<String>,
-    code: Option<String>,
+    param: Option<serde_json::Value>,
+    // string for openai, number for some compatible servers
+    code: Option<serde_json::Value>,
+}
+
+impl OpenAIError {
+    fn get_code(&self) -> Option<String> {
+        return match &self.code {
+            Some(serde_json::Value::String(code)) => Some(code.clone()),
+            Some(serde_json::Value::Null) | None => None,
+            Some(code) => Some(code.to_string()),
+        };
+    }
+
+    fn to_error(&self, status: Option<StatusCode>) -> AirError {
+        let message = self.to_string();
+        match self.get_code().as_deref() {
+            Some("invalid_api_key") => AirError::Auth(message),
+            Some("model_not_found") => AirError::ModelLoad(message),
+            Some("context_length_exceeded") => AirError::ContextOverflow(message),
+            Some("rate_limit_exceeded") | Some("insufficient_quota") => {
+                AirError::RateLimit(message)
+            }
+            _ => match status {
+                Some(status) => AirError::from_status(status, message),
+                None => AirError::Backend(message),
+            },
+        }
+    }
+}
+
+impl fmt::Display for OpenAIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message.as_deref().unwrap_or("Unknown error"))?;
+        let mut details = vec![];
+        if let Some(error_type) = &self.error_type {
+            details.push(format!("type: {}", error_type));
+        }
+        if let Some(param) = self.param.as_ref().filter(|p| !p.is_null()) {
+            details.push(format!("param: {}", param));
+        }
+        if let Some(code) = self.get_code() {
+            details.push(format!("code: {}", code));
+        }
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -53,17 +129,45 @@ pub struct OpenAI {
     pub apikey: String,
     pub model: String,
     pub system: Option<String>,
+    pub base_url: String,
+    pub headers: HashMap<String, String>,
+    pub retry: Retry,
+    pub usage: Option<Usage>,
+    pub tools: Option<ToolRegistry>,
+    pub images: Vec<ContentPart>,
+    pub output: Output,
+    pub finish_reason: Option<String>,
+    pub sampling: Sampling,
+    /// Ask for the usage at the end of the stream (`stream_options`).
+    pub stream_usage: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamFunction {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamToolCall {
+    index: usize,
+    id: Option<String>,
+    #[serde(rename = "type")]
+    call_type: Option<String>,
+    function: Option<StreamFunction>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct StreamDelta {
     content: Option<String>,
+    tool_calls: Option<Vec<StreamToolCall>>,
 }
 #[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct StreamChoice {
     index: Option<u32>,
     delta: Option<StreamDelta>,
+    finish_reason: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -75,27 +179,101 @@ pub struct StreamChunk {
     model: Option<String>,
     system_fingerprint: Option<String>,
     choices: Option<Vec<StreamChoice>>,
+    // last chunk only, with stream_options.include_usage
+    usage: Option<OpenAIUsage>,
+    error: Option<OpenAIError>,
 }
 
-fn parse_data(line: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // println!("LINE => '{}'", line);
-    let mut message = String::new();
-    if line.starts_with("data: ") && !line.starts_with("data: [DONE]") {
-        let json: StreamChunk = serde_json::from_str(&line[6..])?;
+/// What a single request streamed back.
+#[derive(Default)]
+struct Answer {
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    usage: Option<Usage>,
+    finish_reason: Option<String>,
+}
 
-        if let Some(choices) = json.choices {
-            for c in choices {
-                if let Some(delta) = c.delta {
-                    if let Some(content) = delta.content {
-                        print!("{}", content);
-                        message.push_str(&content);
-                        std::io::stdout().flush().unwrap();
-                    }
+fn parse_data(json: StreamChunk, answer: &mut Answer, output: Output) {
+    if let Some(choices) = json.choices {
+        for c in choices {
+            if c.finish_reason.is_some() {
+                answer.finish_reason = c.finish_reason;
+            }
+            if let Some(delta) = c.delta {
+                if let Some(content) = delta.content {
+                    output.token(&content);
+                    answer.content.push_str(&content);
+                }
+                for call in delta.tool_calls.unwrap_or_default() {
+                    add_tool_call(&mut answer.tool_calls, call);
                 }
             }
         }
     }
-    Ok(message)
+}
+
+/// Tool calls are streamed in pieces : the first delta of a call carries its
+/// id and name, the following ones chunks of the json arguments.
+fn add_tool_call(calls: &mut Vec<ToolCall>, delta: StreamToolCall) {
+    if calls.len() <= delta.index {
+        calls.resize(delta.index + 1, ToolCall::default());
+    }
+    let call = &mut calls[delta.index];
+    if let Some(id) = delta.id {
+        call.id = id;
+    }
+    if let Some(call_type) = delta.call_type {
+        call.call_type = call_type;
+    }
+    if let Some(function) = delta.function {
+        call.function
+            .name
+            .push_str(&function.name.unwrap_or_default());
+        call.function
+            .arguments
+            .push_str(&function.arguments.unwrap_or_default());
+    }
+}
+
+/// Append the content of a stream event to `message`, a malformed event
+/// is logged and skipped so it doesn't abort the whole answer, an error
+/// event does.
+fn handle_event(event: &SseEvent, answer: &mut Answer, output: Output) -> Result<(), AirError> {
+    if event.is_done() {
+        return Ok(());
+    }
+    let json: StreamChunk = match serde_json::from_str(&event.data) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("Skipping malformed event ({}) : {}", e, event.data);
+            return Ok(());
+        }
+    };
+    if let Some(error) = &json.error {
+        return Err(error.to_error(None));
+    }
+    if let Some(u) = &json.usage {
+        answer.usage = Some(Usage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+        });
+    }
+    parse_data(json, answer, output);
+    Ok(())
+}
+
+/// Decode the error object of a non 2xx response.
+async fn get_error(response: Response) -> AirError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    log::debug!("{} : {}", status, body);
+
+    match serde_json::from_str::<OpenAIResponse>(&body) {
+        Ok(OpenAIResponse {
+            error: Some(error), ..
+        }) => error.to_error(Some(status)),
+        _ => AirError::from_status(status, format!("{} {}", status, body.trim())),
+    }
 }
 
 #[async_trait]
@@ -112,19 +290,44 @@ impl IChat for OpenAI {
         self.model = model
     }
 
+    fn get_model(&self) -> String {
+        return self.model.clone();
+    }
+
+    fn get_usage(&self) -> Option<Usage> {
+        return self.usage;
+    }
+
+    fn get_finish_reason(&self) -> Option<String> {
+        return self.finish_reason.clone();
+    }
+
+    fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = Some(tools);
+    }
+
+    fn set_images(&mut self, images: Vec<ContentPart>) {
+        self.images = images;
+    }
+
     async fn chat(
         &mut self,
         prompt: String,
         history: Option<Vec<Message>>,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<String, AirError> {
         let mut messages: Vec<Message> = vec![];
 
         if let Some(sys) = &self.system {
-            if sys.len() > 0 {
-                messages.push(Message {
-                    role: Role::System,
-                    content: sys.to_string(),
-                });
+            if !sys.is_empty() {
+                messages.push(Message::new(Role::System, sys.to_string()));
             }
         }
 
@@ -134,63 +337,155 @@ impl IChat for OpenAI {
             }
         }
 
-        messages.push(Message {
-            role: Role::User,
-            content: prompt.to_string(),
-        });
+        messages.push(Message::with_images(
+            Role::User,
+            prompt.to_string(),
+            &self.images,
+        ));
 
+        self.usage = None;
+        let mut rounds: usize = 0;
+        loop {
+            let answer = self.send(&messages).await?;
+            self.finish_reason = answer.finish_reason.clone();
+            if let Some(usage) = &answer.usage {
+                self.usage.get_or_insert(Usage::default()).add(usage);
+            }
+
+            let tools = match &self.tools {
+                Some(tools) if !answer.tool_calls.is_empty() => tools,
+                _ => return Ok(answer.content),
+            };
+            rounds += 1;
+            if rounds > MAX_ROUNDS {
+                return Err(AirError::Backend(format!(
+                    "Still calling tools after {} rounds, giving up.",
+                    MAX_ROUNDS
+                )));
+            }
+
+            let calls = answer.tool_calls;
+            let mut results = vec![];
+            for call in &calls {
+                results.push(Message::tool_result(
+                    call.id.clone(),
+                    tools.call(call).await,
+                ));
+            }
+            messages.push(Message {
+                tool_calls: Some(calls),
+                ..Message::new(Role::Assistant, answer.content)
+            });
+            messages.extend(results);
+        }
+    }
+}
+
+impl OpenAI {
+    pub fn new(
+        apikey: String,
+        base_url: String,
+        headers: HashMap<String, String>,
+        retry: Retry,
+    ) -> Self {
+        return OpenAI {
+            apikey,
+            model: crate::setup::DEFAULT_MODEL.to_string(),
+            system: None,
+            base_url,
+            headers,
+            retry,
+            usage: None,
+            tools: None,
+            images: vec![],
+            output: Output::Text,
+            finish_reason: None,
+            sampling: Sampling::default(),
+            stream_usage: false,
+        };
+    }
+
+    /// Strict OpenAI compatible servers reject the unknown fields.
+    pub fn set_stream_usage(&mut self, stream_usage: bool) {
+        self.stream_usage = stream_usage;
+    }
+
+    /// One request, streamed to stdout.
+    async fn send(&self, messages: &Vec<Message>) -> Result<Answer, AirError> {
         let completion = OpenAICompletion {
             model: &self.model,
             stream: true,
+            stream_options: self.stream_usage.then_some(StreamOptions {
+                include_usage: true,
+            }),
+            sampling: self.sampling,
+            tools: self.tools.as_ref().map(|tools| {
+                tools
+                    .definitions()
+                    .into_iter()
+                    .map(|function| OpenAITool {
+                        tool_type: "function",
+                        function,
+                    })
+                    .collect()
+            }),
             messages,
         };
         let serialized: String = serde_json::to_string_pretty(&completion)?;
         log::debug!("{}\n", serialized);
 
+        let url = self.get_url();
+        log::debug!("POST {}", url);
+
         let client: Client = Client::new();
-        let response = client
-            .post(OPENAI_URL)
-            .header(HEADER_AUTHORIZATION, format!("Bearer {}", self.apikey))
-            .header(HEADER_CONTENT_TYPE, CONTENT_TYPE_JSON)
-            .body(serialized)
-            .send()
-            .await?;
+        let mut request = client
+            .post(url)
+            .header(HEADER_CONTENT_TYPE, CONTENT_TYPE_JSON);
+        // local servers usually don't need any key
+        if !self.apikey.is_empty() {
+            request = request.header(HEADER_AUTHORIZATION, format!("Bearer {}", self.apikey));
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = self.retry.send(request.body(serialized)).await?;
+        if !response.status().is_success() {
+            return Err(get_error(response).await);
+        }
 
         let mut stream = response.bytes_stream();
-        let mut message: String = String::new();
-        let mut buffer: Vec<u8> = vec![];
+        let mut answer = Answer::default();
+        let mut decoder = SseDecoder::new();
 
         while let Some(chunk) = stream.next().await {
             match chunk {
                 Ok(bytes) => {
-                    for c in bytes {
-                        if c == b'\n' {
-                            if buffer.len() > 0 {
-                                let line = parse_data(&String::from_utf8(buffer.clone())?)?;
-                                message.push_str(&line);
-                                message.push('\n');
-                            }
-                            buffer = vec![];
-                        } else {
-                            buffer.push(c);
-                        }
+                    for event in decoder.push(&bytes) {
+                        handle_event(&event, &mut answer, self.output)?;
                     }
                 }
                 Err(e) => {
-                    log::error!("{}", e);
+                    // a truncated answer isn't an answer
+                    self.output.end();
+                    return Err(AirError::Network(format!("Stream interrupted : {}", e)));
                 }
             }
         }
-        Ok(message)
+        for event in decoder.finish() {
+            handle_event(&event, &mut answer, self.output)?;
+        }
+        self.output.end();
+        Ok(answer)
     }
-}
 
-impl OpenAI {
-    pub fn new(apikey: String) -> Self {
-        return OpenAI {
-            apikey,
-            model: crate::setup::DEFAULT_MODEL.to_string(),
-            system: None,
-        };
+    /// Chat completions endpoint, `base_url` is expected to be the API root
+    /// (ie: `https://api.openai.com/v1`, `http://localhost:8080/v1`), a full
+    /// endpoint is used as is.
+    fn get_url(&self) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        if base.ends_with(CHAT_COMPLETIONS) {
+            return base.to_string();
+        }
+        return format!("{}{}", base, CHAT_COMPLETIONS);
     }
 }
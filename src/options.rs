@@ -1,101 +1,772 @@
-use getopts::Options;
+use crate::{output::Output, setup::Setup};
+use chrono::NaiveDate;
+use getopts::{Matches, Options};
 
 const PKG_NAME: Option<&str> = option_env!("CARGO_PKG_NAME");
 const PKG_VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 const PKG_AUTHOR: Option<&str> = option_env!("CARGO_PKG_AUTHORS");
 
+/// `air [ask] <prompt>` chats, the other subcommands manage air itself.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Ask,
+    Usage,
+    History(HistoryAction),
+    Models(ModelsAction),
+    Config(ConfigAction),
+    Templates(TemplatesAction),
+    /// `air completions <shell>`
+    Completions(String),
+    /// `air __complete <kind>`, names for the completion scripts.
+    Complete(String),
+}
+
+/// Session name, the current session when `None`.
+#[derive(Debug, PartialEq)]
+pub enum HistoryAction {
+    List,
+    Show(Option<String>),
+    Clear(Option<String>),
+    Export(Export),
+    Rename(String, String),
+    Delete(String),
+    /// Every term must match.
+    Search(Vec<String>, Filter),
+}
+
+/// Messages selection of `air history search` and `export`.
+#[derive(Debug, PartialEq)]
+pub struct Filter {
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub backend: Option<String>,
+    pub session: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+/// `air history export`, a session or (`all`) every message archive included.
+#[derive(Debug, PartialEq)]
+pub struct Export {
+    pub session: Option<String>,
+    pub all: bool,
+    pub format: ExportFormat,
+    pub file: Option<String>,
+    pub filter: Filter,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ModelsAction {
+    List,
+    Scan(String),
+    Info(String),
+    Remove(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplatesAction {
+    List,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigAction {
+    Show,
+    Edit,
+    Validate,
+}
+
+/// Shell completion of an option value or of a subcommand argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Complete {
+    Nothing,
+    Path,
+    Directory,
+    Values(&'static [&'static str]),
+    /// Names listed by `air __complete <kind>` (models, remotes, personas,
+    /// sessions or templates), read from the setup at completion time.
+    Names(&'static str),
+}
+
+/// An option of `air ask`, a flag when `hint` is empty.
+pub struct OptionSpec {
+    pub short: &'static str,
+    pub long: &'static str,
+    pub description: &'static str,
+    pub hint: &'static str,
+    pub multi: bool,
+    pub complete: Complete,
+}
+
+/// A management subcommand and its actions (with their argument).
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Options of the actions, besides `-v` and `-h`.
+    pub options: &'static [OptionSpec],
+    pub actions: &'static [(&'static str, Complete)],
+}
+
+const fn flag(short: &'static str, long: &'static str, description: &'static str) -> OptionSpec {
+    return OptionSpec {
+        short,
+        long,
+        description,
+        hint: "",
+        multi: false,
+        complete: Complete::Nothing,
+    };
+}
+
+const fn value(
+    short: &'static str,
+    long: &'static str,
+    description: &'static str,
+    hint: &'static str,
+    complete: Complete,
+) -> OptionSpec {
+    return OptionSpec {
+        short,
+        long,
+        description,
+        hint,
+        multi: false,
+        complete,
+    };
+}
+
+const fn multi(
+    short: &'static str,
+    long: &'static str,
+    description: &'static str,
+    hint: &'static str,
+    complete: Complete,
+) -> OptionSpec {
+    return OptionSpec {
+        short,
+        long,
+        description,
+        hint,
+        multi: true,
+        complete,
+    };
+}
+
+pub const ASK_OPTIONS: &[OptionSpec] = &[
+    value(
+        "l",
+        "local",
+        "Run local model (llama-cpp or ollama)",
+        "name",
+        Complete::Names("models"),
+    ),
+    value(
+        "r",
+        "remote",
+        "Run remote profile (OpenAI compatible)",
+        "name",
+        Complete::Names("remotes"),
+    ),
+    flag("a", "anthropic", "Use Anthropic (claude)"),
+    value(
+        "S",
+        "session",
+        "Use (and switch to) a named session",
+        "name",
+        Complete::Names("sessions"),
+    ),
+    multi(
+        "i",
+        "image",
+        "Attach an image (vision models)",
+        "file",
+        Complete::Path,
+    ),
+    multi(
+        "f",
+        "file",
+        "Attach a file or directory as context",
+        "path",
+        Complete::Path,
+    ),
+    flag(
+        "P",
+        "stdin-prompt",
+        "Piped stdin is the prompt (context by default)",
+    ),
+    flag(
+        "x",
+        "shell",
+        "Propose a shell command (execute, edit or copy)",
+    ),
+    flag(
+        "T",
+        "tools",
+        "Allow the model to call local tools (confirmed)",
+    ),
+    flag("u", "usage", "Display token usage and cost"),
+    value(
+        "o",
+        "output",
+        "Output format (text, json or jsonl)",
+        "format",
+        Complete::Values(&["text", "json", "jsonl"]),
+    ),
+    flag("v", "verbose", "Verbose/debug"),
+    flag("m", "markdown", "Toggle markdown"),
+    value(
+        "s",
+        "system-prompt",
+        "Set system prompt (empty for None)",
+        "text",
+        Complete::Nothing,
+    ),
+    value(
+        "p",
+        "persona",
+        "Use a persona of the setup",
+        "name",
+        Complete::Names("personas"),
+    ),
+    value(
+        "t",
+        "template",
+        "Use a prompt template",
+        "name",
+        Complete::Names("templates"),
+    ),
+    value(
+        "R",
+        "reopen",
+        "Continue from a message (see history search)",
+        "id",
+        Complete::Nothing,
+    ),
+    multi(
+        "",
+        "var",
+        "Template variable (@file or - for stdin)",
+        "name=value",
+        Complete::Nothing,
+    ),
+    flag("h", "help", "Help"),
+];
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "ask",
+        description: "Ask a question (default)",
+        options: &[],
+        actions: &[],
+    },
+    CommandSpec {
+        name: "history",
+        description: "Manage the sessions",
+        options: HISTORY_OPTIONS,
+        actions: &[
+            ("list", Complete::Nothing),
+            ("show", Complete::Names("sessions")),
+            ("clear", Complete::Names("sessions")),
+            ("export", Complete::Names("sessions")),
+            ("rename", Complete::Names("sessions")),
+            ("delete", Complete::Names("sessions")),
+            ("search", Complete::Nothing),
+        ],
+    },
+    CommandSpec {
+        name: "models",
+        description: "Manage the local models",
+        options: &[],
+        actions: &[
+            ("list", Complete::Nothing),
+            ("scan", Complete::Directory),
+            ("info", Complete::Names("models")),
+            ("remove", Complete::Names("models")),
+        ],
+    },
+    CommandSpec {
+        name: "config",
+        description: "Manage the setup file",
+        options: &[],
+        actions: &[
+            ("show", Complete::Nothing),
+            ("edit", Complete::Nothing),
+            ("validate", Complete::Nothing),
+        ],
+    },
+    CommandSpec {
+        name: "templates",
+        description: "Manage the prompt templates",
+        options: &[],
+        actions: &[("list", Complete::Nothing)],
+    },
+    CommandSpec {
+        name: "usage",
+        description: "Token usage and cost report",
+        options: &[],
+        actions: &[],
+    },
+    CommandSpec {
+        name: "completions",
+        description: "Print a shell completion script",
+        options: &[],
+        actions: &[
+            ("bash", Complete::Nothing),
+            ("zsh", Complete::Nothing),
+            ("fish", Complete::Nothing),
+        ],
+    },
+];
+
+/// Filters of `air history search` and options of `export`.
+pub const HISTORY_OPTIONS: &[OptionSpec] = &[
+    value(
+        "",
+        "since",
+        "Messages since this date",
+        "YYYY-MM-DD",
+        Complete::Nothing,
+    ),
+    value(
+        "",
+        "until",
+        "Messages until this date",
+        "YYYY-MM-DD",
+        Complete::Nothing,
+    ),
+    value(
+        "b",
+        "backend",
+        "Messages of a backend or model (openai, anthropic, ollama, llama ...)",
+        "name",
+        Complete::Nothing,
+    ),
+    value(
+        "S",
+        "session",
+        "Messages of a session",
+        "name",
+        Complete::Names("sessions"),
+    ),
+    flag("a", "all", "Export all the sessions and the archive"),
+    value(
+        "F",
+        "format",
+        "Export format (json, md or html)",
+        "format",
+        Complete::Values(&["json", "md", "html"]),
+    ),
+    value("o", "output", "Export to a file", "file", Complete::Path),
+];
+
+/// Hidden subcommand used by the completion scripts.
+pub const COMPLETE_COMMAND: &str = "__complete";
+
 pub struct CommandLine {
+    pub command: Command,
     pub verbose: bool,
-    pub clear: bool,
     pub markdown: bool,
-    pub list: bool,
+    toggle_markdown: bool,
     pub system: Option<String>,
+    pub persona: Option<String>,
+    pub template: Option<String>,
+    pub vars: Vec<String>,
+    pub reopen: Option<String>,
     pub prompt: String,
     pub local: Option<String>,
-    pub usage: String,
-    pub scan: Option<String>,
+    pub remote: Option<String>,
+    pub anthropic: bool,
+    pub show_usage: bool,
+    pub output: Output,
+    pub tools: bool,
+    pub shell: bool,
+    pub images: Vec<String>,
+    pub files: Vec<String>,
+    pub stdin_prompt: bool,
+    pub session: Option<String>,
 }
 
 impl Default for CommandLine {
     fn default() -> CommandLine {
         CommandLine {
-            verbose: if cfg!(debug_assertions) { true } else { false },
-            clear: false,
+            command: Command::Ask,
+            verbose: cfg!(debug_assertions),
             markdown: true,
-            list: false,
+            toggle_markdown: false,
             system: None,
+            persona: None,
+            template: None,
+            vars: vec![],
+            reopen: None,
             prompt: "".to_string(),
             local: None,
-            usage: "".to_string(),
-            scan: None,
+            remote: None,
+            anthropic: false,
+            show_usage: false,
+            output: Output::Text,
+            tools: false,
+            shell: false,
+            images: vec![],
+            files: vec![],
+            stdin_prompt: false,
+            session: None,
         }
     }
 }
 
 #[allow(dead_code)]
 impl CommandLine {
-    pub fn new(default_system: &str, default_markdown: bool) -> Result<Self, String> {
-        let args: Vec<String> = std::env::args().collect();
+    /// Parse the command line, the error is the message to display (help
+    /// included).
+    pub fn new() -> Result<Self, String> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let rest = args.get(1..).unwrap_or(&[]);
+        let command = match args.first().map(|a| a.as_str()) {
+            Some("ask") => return CommandLine::parse_ask(rest),
+            Some(COMPLETE_COMMAND) => {
+                return Ok(CommandLine {
+                    command: Command::Complete(rest.join(" ")),
+                    verbose: false,
+                    ..Default::default()
+                })
+            }
+            Some("usage") => CommandLine::parse_usage(rest)?,
+            Some("history") => CommandLine::parse_history(rest)?,
+            Some("models") => CommandLine::parse_models(rest)?,
+            Some("config") => CommandLine::parse_config(rest)?,
+            Some("templates") => CommandLine::parse_templates(rest)?,
+            Some("completions") => CommandLine::parse_completions(rest)?,
+            _ => None,
+        };
+        // a prompt starting with a command name ("air config nginx proxy")
+        return match command {
+            Some(command) => Ok(command),
+            None => CommandLine::parse_ask(&args),
+        };
+    }
+
+    /// Defaults from the setup file, when not given on the command line.
+    /// The system prompt is resolved once the session is loaded.
+    pub fn apply_setup(&mut self, setup: &Setup) {
+        // toggle markdown if option -m is present
+        self.markdown = setup.get_markdown() != self.toggle_markdown;
+    }
+
+    fn get_header() -> String {
+        return format!(
+            "{} v{} (c) {}",
+            CommandLine::get_name(),
+            PKG_VERSION.unwrap_or("?.?.?"),
+            PKG_AUTHOR.unwrap_or("???")
+        );
+    }
+
+    fn get_name() -> &'static str {
+        return PKG_NAME.unwrap_or("aid");
+    }
+
+    fn get_options(specs: &[OptionSpec]) -> Options {
         let mut opts = Options::new();
+        for spec in specs {
+            match (spec.hint, spec.multi) {
+                ("", _) => opts.optflag(spec.short, spec.long, spec.description),
+                (hint, true) => opts.optmulti(spec.short, spec.long, spec.description, hint),
+                (hint, false) => opts.optopt(spec.short, spec.long, spec.description, hint),
+            };
+        }
+        return opts;
+    }
 
-        opts.optopt("l", "local", "Run local model (llama-cpp)", "name");
-        opts.optopt("x", "scan", "Scan for local models (llama-cpp)", "folder");
-        opts.optflag("L", "list", "List local models (llama-cpp)");
-        opts.optflag("c", "clear", "Clear history");
-        opts.optflag("v", "verbose", "Verbose/debug");
-        opts.optflag("m", "markdown", "Toggle markdown");
-        opts.optflag("s", "system-prompt", "Set system prompt (empty for None)");
-        opts.optflag("h", "help", "Help");
+    fn parse_ask(args: &[String]) -> Result<Self, String> {
+        let opts = CommandLine::get_options(ASK_OPTIONS);
 
-        let matches = match opts.parse(&args[1..]) {
+        let matches = match opts.parse(args) {
             Ok(matches) => matches,
             Err(fail) => return Err(fail.to_string()),
         };
 
-        let pname = PKG_NAME.unwrap_or("aid");
-        let header = format!(
-            "{} v{} (c) {}",
+        let pname = CommandLine::get_name();
+        let usage = opts.usage(&format!(
+            "{}\nUsage: {} [ask] [options] <prompt>\n       {} history|models|config|templates|completions|usage <command> [-h]",
+            CommandLine::get_header(),
             pname,
-            PKG_VERSION.unwrap_or("?.?.?"),
-            PKG_AUTHOR.unwrap_or("???")
-        );
-        let usage = opts.usage(&format!("{}\nUsage: {} [options] <prompt>", header, pname));
+            pname
+        ));
         if matches.opt_present("h") {
             return Err(usage);
         }
-        // toggle markdown if option -m is present
-        let mut md: bool = matches.opt_present("m");
-        if md {
-            md = !default_markdown;
-        } else {
-            md = default_markdown;
-        }
 
-        let mut sys: String = default_system.to_string();
-        if matches.opt_present("m") {
-            sys = matches.opt_str("s").unwrap_or("".to_string());
-        }
+        let output = match matches.opt_str("o") {
+            Some(output) => Output::parse(&output)?,
+            None => Output::Text,
+        };
+        let toggle_markdown = matches.opt_present("m");
 
         return Ok(CommandLine {
+            command: Command::Ask,
             verbose: matches.opt_present("v"),
-            clear: matches.opt_present("c"),
-            markdown: md,
-            system: Some(sys),
+            markdown: !toggle_markdown,
+            toggle_markdown,
+            system: matches.opt_str("s"),
+            persona: matches.opt_str("p"),
+            template: matches.opt_str("t"),
+            vars: matches.opt_strs("var"),
+            reopen: matches.opt_str("R"),
             prompt: matches.free.join(" ").trim().to_string(),
             local: matches.opt_str("l"),
-            list: matches.opt_present("L"),
-            scan: matches.opt_str("x"),
-            usage,
+            remote: matches.opt_str("r"),
+            anthropic: matches.opt_present("a"),
+            show_usage: matches.opt_present("u"),
+            output,
+            tools: matches.opt_present("T"),
+            shell: matches.opt_present("x"),
+            images: matches.opt_strs("i"),
+            files: matches.opt_strs("f"),
+            stdin_prompt: matches.opt_present("P"),
+            session: matches.opt_str("S"),
         });
     }
 
+    /// Management subcommands take `-v`, `-h` and their own options. `None`
+    /// when the options don't parse (the arguments are a prompt).
+    fn parse_command(
+        args: &[String],
+        name: &str,
+        commands: &str,
+        options: &[OptionSpec],
+    ) -> Result<Option<(Matches, String)>, String> {
+        let mut opts = CommandLine::get_options(options);
+        opts.optflag("v", "verbose", "Verbose/debug");
+        opts.optflag("h", "help", "Help");
+
+        let usage = opts.usage(&format!(
+            "{}\nUsage: {} {} {}",
+            CommandLine::get_header(),
+            CommandLine::get_name(),
+            name,
+            commands
+        ));
+        let matches = match opts.parse(args) {
+            Ok(matches) => matches,
+            Err(_) => return Ok(None),
+        };
+        if matches.opt_present("h") {
+            return Err(usage);
+        }
+        return Ok(Some((matches, usage)));
+    }
+
+    fn with_command(command: Command, matches: &Matches) -> Result<Option<Self>, String> {
+        return Ok(Some(CommandLine {
+            command,
+            verbose: matches.opt_present("v"),
+            ..Default::default()
+        }));
+    }
+
+    /// Arguments not matching a subcommand : its usage when there are none,
+    /// otherwise they are a prompt.
+    fn mismatch(matches: &Matches, usage: String) -> Result<Option<Self>, String> {
+        if matches.free.is_empty() {
+            return Err(usage);
+        }
+        return Ok(None);
+    }
+
+    fn parse_usage(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((matches, _)) =
+            CommandLine::parse_command(args, "usage", "\n\nToken usage and cost report", &[])?
+        else {
+            return Ok(None);
+        };
+        if !matches.free.is_empty() {
+            return Ok(None);
+        }
+        return CommandLine::with_command(Command::Usage, &matches);
+    }
+
+    fn parse_history(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((matches, usage)) = CommandLine::parse_command(
+            args,
+            "history",
+            "<command>\n\n\
+             Commands:\n    \
+             list                List sessions\n    \
+             show [session]      Display the messages\n    \
+             clear [session]     Clear the messages\n    \
+             export [session]    Write a transcript (md, html or json messages)\n    \
+             rename <session> <name>\n                        \
+             Rename a session\n    \
+             delete <session>    Delete a session\n    \
+             search <terms>...   Search all the messages (expired included)",
+            HISTORY_OPTIONS,
+        )?
+        else {
+            return Ok(None);
+        };
+        let free: Vec<&str> = matches.free.iter().map(|s| s.as_str()).collect();
+        let session = free.get(1).map(|s| s.to_string());
+        let filter = Filter {
+            since: CommandLine::parse_date(&matches, "since", &usage)?,
+            until: CommandLine::parse_date(&matches, "until", &usage)?,
+            backend: matches.opt_str("b"),
+            session: matches.opt_str("S"),
+        };
+        let format = match matches.opt_str("F").as_deref() {
+            Some("md") | Some("markdown") => ExportFormat::Markdown,
+            Some("html") => ExportFormat::Html,
+            None | Some("json") => ExportFormat::Json,
+            Some(format) => return Err(format!("Invalid export format '{}'\n\n{}", format, usage)),
+        };
+        let action = match free.as_slice() {
+            ["list"] => HistoryAction::List,
+            ["show"] | ["show", _] => HistoryAction::Show(session),
+            ["clear"] | ["clear", _] => HistoryAction::Clear(session),
+            ["export"] | ["export", _] => HistoryAction::Export(Export {
+                session,
+                all: matches.opt_present("a"),
+                format,
+                file: matches.opt_str("o"),
+                filter,
+            }),
+            ["rename", name, new_name] => {
+                HistoryAction::Rename(name.to_string(), new_name.to_string())
+            }
+            ["delete", name] => HistoryAction::Delete(name.to_string()),
+            ["search", terms @ ..] if !terms.is_empty() => {
+                HistoryAction::Search(terms.iter().map(|t| t.to_string()).collect(), filter)
+            }
+            _ => return CommandLine::mismatch(&matches, usage),
+        };
+        return CommandLine::with_command(Command::History(action), &matches);
+    }
+
+    fn parse_date(matches: &Matches, name: &str, usage: &str) -> Result<Option<NaiveDate>, String> {
+        return match matches.opt_str(name) {
+            Some(date) => match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+                Ok(date) => Ok(Some(date)),
+                Err(e) => Err(format!("Invalid date '{}' : {}\n\n{}", date, e, usage)),
+            },
+            None => Ok(None),
+        };
+    }
+
+    fn parse_models(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((matches, usage)) = CommandLine::parse_command(
+            args,
+            "models",
+            "<command>\n\n\
+             Commands:\n    \
+             list                List local models (llama-cpp and ollama)\n    \
+             scan <folder>       Add the gguf models of a folder (llama-cpp)\n    \
+             info <name>         Display a local model\n    \
+             remove <name>       Remove a model from the setup (llama-cpp)",
+            &[],
+        )?
+        else {
+            return Ok(None);
+        };
+        let free: Vec<&str> = matches.free.iter().map(|s| s.as_str()).collect();
+        let action = match free.as_slice() {
+            ["list"] => ModelsAction::List,
+            ["scan", folder] => ModelsAction::Scan(folder.to_string()),
+            ["info", name] => ModelsAction::Info(name.to_string()),
+            ["remove", name] => ModelsAction::Remove(name.to_string()),
+            _ => return CommandLine::mismatch(&matches, usage),
+        };
+        return CommandLine::with_command(Command::Models(action), &matches);
+    }
+
+    fn parse_templates(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((matches, usage)) = CommandLine::parse_command(
+            args,
+            "templates",
+            "<command>\n\n\
+             Commands:\n    \
+             list                List prompt templates",
+            &[],
+        )?
+        else {
+            return Ok(None);
+        };
+        let free: Vec<&str> = matches.free.iter().map(|s| s.as_str()).collect();
+        let action = match free.as_slice() {
+            ["list"] => TemplatesAction::List,
+            _ => return CommandLine::mismatch(&matches, usage),
+        };
+        return CommandLine::with_command(Command::Templates(action), &matches);
+    }
+
+    fn parse_config(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((matches, usage)) = CommandLine::parse_command(
+            args,
+            "config",
+            "<command>\n\n\
+             Commands:\n    \
+             show                Display the setup\n    \
+             edit                Edit the setup file ($EDITOR)\n    \
+             validate            Check the setup file",
+            &[],
+        )?
+        else {
+            return Ok(None);
+        };
+        let free: Vec<&str> = matches.free.iter().map(|s| s.as_str()).collect();
+        let action = match free.as_slice() {
+            ["show"] => ConfigAction::Show,
+            ["edit"] => ConfigAction::Edit,
+            ["validate"] => ConfigAction::Validate,
+            _ => return CommandLine::mismatch(&matches, usage),
+        };
+        return CommandLine::with_command(Command::Config(action), &matches);
+    }
+
+    fn parse_completions(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((matches, usage)) = CommandLine::parse_command(
+            args,
+            "completions",
+            "<shell>\n\n\
+             Shells:\n    \
+             bash                source <(air completions bash)\n    \
+             zsh                 air completions zsh > \"${fpath[1]}/_air\"\n    \
+             fish                air completions fish > ~/.config/fish/completions/air.fish",
+            &[],
+        )?
+        else {
+            return Ok(None);
+        };
+        let free: Vec<&str> = matches.free.iter().map(|s| s.as_str()).collect();
+        let shell = match free.as_slice() {
+            [shell @ ("bash" | "zsh" | "fish")] => shell.to_string(),
+            _ => return CommandLine::mismatch(&matches, usage),
+        };
+        return CommandLine::with_command(Command::Completions(shell), &matches);
+    }
+
     pub fn display(&self) {
-        termimad::print_inline(&format!("*CLEAR*      => `{}`\n", self.clear));
+        termimad::print_inline(&format!("*COMMAND*    => `{:?}`\n", self.command));
         termimad::print_inline(&format!("*MARKDOWN*   => `{}`\n", self.markdown));
         termimad::print_inline(&format!("*VERBOSE*    => `{}`\n", self.verbose));
         termimad::print_inline(&format!("*LOCAL*      => `{:?}`\n", self.local));
+        termimad::print_inline(&format!("*REMOTE*     => `{:?}`\n", self.remote));
+        termimad::print_inline(&format!("*ANTHROPIC*  => `{}`\n", self.anthropic));
+        termimad::print_inline(&format!("*OUTPUT*     => `{:?}`\n", self.output));
+        termimad::print_inline(&format!("*TOOLS*      => `{}`\n", self.tools));
+        termimad::print_inline(&format!("*SHELL*      => `{}`\n", self.shell));
+        termimad::print_inline(&format!("*SESSION*    => `{:?}`\n", self.session));
+        termimad::print_inline(&format!("*STDIN*      => `{}`\n", self.stdin_prompt));
+        termimad::print_inline(&format!("*IMAGES*     => `{:?}`\n", self.images));
+        termimad::print_inline(&format!("*FILES*      => `{:?}`\n", self.files));
         termimad::print_inline(&format!("*PROMPT*     => `{}`\n", self.prompt));
         termimad::print_inline(&format!("*SYSTEM*     => `{:?}`\n", self.system));
+        termimad::print_inline(&format!("*PERSONA*    => `{:?}`\n", self.persona));
+        termimad::print_inline(&format!("*TEMPLATE*   => `{:?}`\n", self.template));
+        termimad::print_inline(&format!("*VARS*       => `{:?}`\n", self.vars));
+        termimad::print_inline(&format!("*REOPEN*     => `{:?}`\n", self.reopen));
         termimad::print_inline("___\n");
     }
 }
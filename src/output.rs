@@ -0,0 +1,77 @@
+use crate::ichat::Usage;
+use serde::Serialize;
+use serde_json::json;
+use std::io::Write;
+
+/// How answers are written on stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Output {
+    /// Streamed text.
+    #[default]
+    Text,
+    /// A single json object once answered.
+    Json,
+    /// A json line per streamed token, then the answer.
+    Jsonl,
+}
+
+/// The answer and what produced it, for scripts.
+#[derive(Serialize, Debug)]
+pub struct JsonAnswer<'a> {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<&'a str>,
+    pub backend: &'a str,
+    pub model: &'a str,
+    pub system: Option<&'a str>,
+    pub answer: &'a str,
+    pub finish_reason: Option<String>,
+    pub duration_ms: u128,
+    pub usage: Option<Usage>,
+    pub cost: Option<f64>,
+}
+
+impl Output {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            "jsonl" => Ok(Output::Jsonl),
+            _ => Err(format!("Unknown output '{}' (text, json or jsonl)", value)),
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        return *self != Output::Text;
+    }
+
+    /// A streamed piece of the answer.
+    pub fn token(&self, text: &str) {
+        match self {
+            Output::Text => {
+                print!("{}", text);
+                std::io::stdout().flush().unwrap();
+            }
+            Output::Json => {}
+            Output::Jsonl => println!("{}", json!({ "type": "token", "content": text })),
+        }
+    }
+
+    /// End of a streamed answer.
+    pub fn end(&self) {
+        if *self == Output::Text {
+            println!();
+        }
+    }
+
+    pub fn answer(&self, mut answer: JsonAnswer) -> Result<(), serde_json::Error> {
+        match self {
+            Output::Text => {}
+            Output::Json => println!("{}", serde_json::to_string_pretty(&answer)?),
+            Output::Jsonl => {
+                answer.kind = Some("answer");
+                println!("{}", serde_json::to_string(&answer)?);
+            }
+        }
+        Ok(())
+    }
+}
@@ -8,13 +8,11 @@ pub struct FileInfo {
 }
 
 pub fn get_config_directory() -> String {
-    let config = dirs::config_dir()
-        .unwrap()
-        .join("air");
+    let config = dirs::config_dir().unwrap().join("air");
 
     let path = Path::new(&config);
     if !path.exists() || !path.is_dir() {
-        fs::create_dir_all(&path).unwrap();
+        fs::create_dir_all(path).unwrap();
     }
 
     return config.to_string_lossy().to_string();
@@ -27,4 +25,14 @@ pub fn get_config_path(file: &str) -> FileInfo {
         path: file.to_string_lossy().to_string(),
         exists: file.exists() && file.is_file(),
     };
-}
\ No newline at end of file
+}
+
+/// Session and template names are file names : letters, digits, '-', '_' and
+/// '.' (not leading).
+pub fn is_valid_name(name: &str) -> bool {
+    return !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+}
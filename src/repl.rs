@@ -0,0 +1,254 @@
+use crate::{
+    ask, error::AirError, get_chat, history::History, ichat::IChat, options::CommandLine,
+    path::get_config_path, setup::Setup, tools::ToolRegistry,
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::fs;
+use termimad::crossterm::style::Stylize;
+
+const INPUT_HISTORY: &str = "input_history.txt";
+const PROMPT: &str = "air> ";
+const CONTINUATION: &str = "...> ";
+const MULTILINE: &str = "\"\"\"";
+const HELP: &str = "\
+Commands:
+    /model [name]     Show or set the model
+    /system [prompt]  Show or set the system prompt
+    /local <name>     Switch to a local model (llama-cpp or ollama)
+    /retry            Ask the last prompt again
+    /clear            Clear history
+    /save <file>      Save the conversation (markdown)
+    /help             This help
+    /exit             Quit (or ctrl-d)
+
+End a line with '\\' to continue on the next one, or wrap a block with \"\"\".
+Ctrl-c cancels the current answer.";
+
+/// What the commands of the repl change.
+struct State {
+    system: String,
+    /// Last prompt sent, for /retry.
+    last: Option<String>,
+    answered: bool,
+}
+
+enum Action {
+    Continue,
+    Send(String),
+    Quit,
+}
+
+/// Interactive chat, started when there is no prompt on the command line.
+pub async fn run(
+    mut ichat: Box<dyn IChat>,
+    history: &mut History,
+    setup: &Setup,
+    options: &CommandLine,
+) -> Result<(), AirError> {
+    let mut editor = DefaultEditor::new().map_err(|e| AirError::Io(std::io::Error::other(e)))?;
+    let input_history = get_config_path(INPUT_HISTORY);
+    if input_history.exists {
+        if let Err(e) = editor.load_history(&input_history.path) {
+            log::warn!("{}", e);
+        }
+    }
+
+    let mut state = State {
+        system: options.system.clone().unwrap_or_default(),
+        last: None,
+        answered: false,
+    };
+
+    println!(
+        "{} {} (/help for commands, ctrl-d to quit)",
+        Stylize::bold("air"),
+        ichat.get_model()
+    );
+    while let Some(input) = read_input(&mut editor)? {
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.as_str());
+
+        let prompt = if input.starts_with('/') {
+            let action = command(&input, &mut ichat, history, setup, options, &mut state);
+            match action {
+                Action::Continue => continue,
+                Action::Send(prompt) => prompt,
+                Action::Quit => break,
+            }
+        } else {
+            input
+        };
+
+        state.last = Some(prompt.clone());
+        state.answered = send(&mut ichat, setup, options, history, &state.system, &prompt).await;
+        // images are only attached to the first prompt
+        if !options.images.is_empty() {
+            ichat.set_images(vec![]);
+        }
+    }
+
+    if let Err(e) = editor.save_history(&input_history.path) {
+        log::warn!("{}", e);
+    }
+    Ok(())
+}
+
+/// Read one prompt, `None` at the end of input (ctrl-d).
+fn read_input(editor: &mut DefaultEditor) -> Result<Option<String>, AirError> {
+    let mut lines: Vec<String> = vec![];
+    let mut block = false;
+    loop {
+        let prompt = if lines.is_empty() && !block {
+            PROMPT
+        } else {
+            CONTINUATION
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // ctrl-c : drop the current input
+            Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => return Ok(None),
+            Err(e) => return Err(AirError::Io(std::io::Error::other(e))),
+        };
+
+        if line.trim() == MULTILINE {
+            if block {
+                return Ok(Some(lines.join("\n")));
+            }
+            block = true;
+            continue;
+        }
+        if block {
+            lines.push(line);
+            continue;
+        }
+        match line.strip_suffix('\\') {
+            Some(line) => lines.push(line.to_string()),
+            None => {
+                lines.push(line);
+                return Ok(Some(lines.join("\n")));
+            }
+        }
+    }
+}
+
+/// Ask with the current system prompt, ctrl-c cancels the answer. Returns
+/// true once answered.
+async fn send(
+    ichat: &mut Box<dyn IChat>,
+    setup: &Setup,
+    options: &CommandLine,
+    history: &mut History,
+    system: &str,
+    prompt: &str,
+) -> bool {
+    tokio::select! {
+        result = ask(ichat, setup, options, history, system, prompt) => match result {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("{}", e);
+                false
+            }
+        },
+        _ = tokio::signal::ctrl_c() => {
+            println!();
+            log::warn!("{}", AirError::UserAbort);
+            false
+        }
+    }
+}
+
+fn command(
+    input: &str,
+    ichat: &mut Box<dyn IChat>,
+    history: &mut History,
+    setup: &Setup,
+    options: &CommandLine,
+    state: &mut State,
+) -> Action {
+    let system = &mut state.system;
+    let (name, argument) = match input.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (input.trim(), ""),
+    };
+
+    match name {
+        "/help" => println!("{}", HELP),
+        "/exit" | "/quit" => return Action::Quit,
+        "/model" => {
+            if !argument.is_empty() {
+                ichat.set_model(argument.to_string());
+                history.setup.model = Some(argument.to_string());
+                save(history);
+            }
+            println!("Model : {}", ichat.get_model());
+        }
+        "/system" => {
+            if !argument.is_empty() {
+                *system = argument.to_string();
+                ichat.set_system(system.clone());
+                history.setup.system = Some(system.clone());
+                save(history);
+            }
+            println!("System : {}", system);
+        }
+        "/local" => match get_chat(&Some(argument.to_string()), setup, options) {
+            Ok(mut chat) => {
+                if !system.trim().is_empty() {
+                    chat.set_system(system.clone());
+                }
+                if options.tools {
+                    chat.set_tools(ToolRegistry::new());
+                }
+                chat.set_output(options.output);
+                if let Some(persona) = options.persona.as_ref().and_then(|p| setup.get_persona(p)) {
+                    chat.set_sampling(persona.get_sampling());
+                }
+                println!("Using {} ({})", chat.get_model(), chat.get_name());
+                *ichat = chat;
+                history.setup.local = Some(argument.to_string());
+                history.setup.remote = None;
+                history.setup.anthropic = false;
+                history.setup.model = None;
+                save(history);
+            }
+            Err(e) => log::error!("{}", e),
+        },
+        "/retry" => match &state.last {
+            Some(prompt) => {
+                if state.answered {
+                    history.remove_last();
+                }
+                return Action::Send(prompt.clone());
+            }
+            None => log::error!("Nothing to retry."),
+        },
+        "/clear" => {
+            history.clear();
+            match history.save() {
+                Ok(_) => println!("History cleared."),
+                Err(e) => log::error!("{}", e),
+            }
+        }
+        "/save" => {
+            if argument.is_empty() {
+                log::error!("Usage : /save <file>");
+            } else {
+                match fs::write(argument, history.to_markdown()) {
+                    Ok(_) => println!("Saved to {}", argument),
+                    Err(e) => log::error!("{}", e),
+                }
+            }
+        }
+        _ => log::error!("Unknown command {}, try /help", name),
+    }
+    return Action::Continue;
+}
+
+fn save(history: &mut History) {
+    if let Err(e) = history.save() {
+        log::error!("{}", e);
+    }
+}
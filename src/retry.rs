@@ -0,0 +1,223 @@
+use crate::{error::AirError, setup::RetrySetup};
+use chrono::{DateTime, Utc};
+use reqwest::{header::HeaderMap, RequestBuilder, Response, StatusCode};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const HEADER_RETRY_AFTER: &str = "retry-after";
+const HEADER_RESET_REQUESTS: &str = "x-ratelimit-reset-requests";
+const HEADER_RESET_TOKENS: &str = "x-ratelimit-reset-tokens";
+
+/// Retry policy for the http backends : requests failing with a network
+/// error, 429 or 5xx are sent again with an exponential backoff. Only the
+/// request itself is retried, never a stream which already started.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    attempts: u32,
+    delay: Duration,
+    max_delay: Duration,
+}
+
+impl Retry {
+    pub fn new(setup: &RetrySetup) -> Self {
+        return Retry {
+            attempts: setup.get_attempts().max(1),
+            delay: Duration::from_millis(setup.get_delay()),
+            max_delay: Duration::from_millis(setup.get_max_delay()),
+        };
+    }
+
+    /// Send the request, the last response is returned as is (whatever its
+    /// status) once every attempt has been used.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, AirError> {
+        let mut attempt: u32 = 1;
+        loop {
+            let current = match request.try_clone() {
+                Some(current) => current,
+                // streamed body, can't be sent twice
+                None => return Ok(request.send().await?),
+            };
+            log::info!("Attempt {}/{}", attempt, self.attempts);
+
+            let wait = match current.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    if !Retry::is_retryable(status) || attempt >= self.attempts {
+                        return Ok(response);
+                    }
+                    let wait = self.get_delay(attempt, Some(response.headers()));
+                    log::warn!("Server answered {}, retrying in {:?}", status, wait);
+                    wait
+                }
+                Err(e) => {
+                    if !(e.is_connect() || e.is_timeout()) || attempt >= self.attempts {
+                        return Err(e.into());
+                    }
+                    let wait = self.get_delay(attempt, None);
+                    log::warn!("{}, retrying in {:?}", e, wait);
+                    wait
+                }
+            };
+            tokio::time::sleep(wait).await;
+            attempt += 1;
+        }
+    }
+
+    fn is_retryable(status: StatusCode) -> bool {
+        return status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+    }
+
+    /// Server hint when there is one, exponential backoff with jitter otherwise.
+    fn get_delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+        if let Some(hint) = headers.and_then(Retry::get_hint) {
+            return hint.min(self.max_delay);
+        }
+        let backoff = self
+            .delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        // keep between 50% and 100% of the backoff
+        let half = backoff / 2;
+        return half + half.mul_f64(Retry::jitter());
+    }
+
+    fn get_hint(headers: &HeaderMap) -> Option<Duration> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        if let Some(value) = header(HEADER_RETRY_AFTER) {
+            if let Ok(seconds) = value.trim().parse::<u64>() {
+                return Some(Duration::from_secs(seconds));
+            }
+            if let Ok(date) = DateTime::parse_from_rfc2822(value.trim()) {
+                let wait = date.with_timezone(&Utc) - Utc::now();
+                return Some(wait.to_std().unwrap_or(Duration::ZERO));
+            }
+        }
+        // openai : wait for the longest of both limits
+        [HEADER_RESET_REQUESTS, HEADER_RESET_TOKENS]
+            .iter()
+            .filter_map(|name| header(name).and_then(parse_duration))
+            .max()
+    }
+
+    fn jitter() -> f64 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        return (nanos % 1000) as f64 / 1000.0;
+    }
+}
+
+/// Parse go style durations used by openai headers : `20ms`, `1s`, `6m0s`, `1h2m3.5s`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut parsed = false;
+    let mut chars = value.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            parsed |= c.is_ascii_digit();
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            'h' => amount * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                amount / 1000.0
+            }
+            'm' => amount * 60.0,
+            's' => amount,
+            _ => return None,
+        };
+    }
+    if !number.is_empty() {
+        // bare number, seconds
+        total += number.parse::<f64>().ok()?;
+    }
+    // an empty header isn't a delay
+    if !parsed {
+        return None;
+    }
+    return Some(Duration::from_secs_f64(total));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(values: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        return headers;
+    }
+
+    #[test]
+    fn go_durations() {
+        assert_eq!(parse_duration("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_duration("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_duration(" 0.5s "), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2"), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("."), None);
+        assert_eq!(parse_duration("5d"), None);
+        assert_eq!(parse_duration("1.2.3s"), None);
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        let hint = Retry::get_hint(&headers(&[(HEADER_RETRY_AFTER, " 7 ")]));
+        assert_eq!(hint, Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let past = headers(&[(HEADER_RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT")]);
+        assert_eq!(Retry::get_hint(&past), Some(Duration::ZERO));
+
+        let date = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let hint = Retry::get_hint(&headers(&[(HEADER_RETRY_AFTER, &date)])).unwrap();
+        assert!(hint > Duration::from_secs(55) && hint <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn ratelimit_reset_takes_the_longest() {
+        let hint = Retry::get_hint(&headers(&[
+            (HEADER_RESET_REQUESTS, "20ms"),
+            (HEADER_RESET_TOKENS, "6m0s"),
+        ]));
+        assert_eq!(hint, Some(Duration::from_secs(360)));
+    }
+
+    #[test]
+    fn invalid_headers_are_no_hint() {
+        let hint = Retry::get_hint(&headers(&[
+            (HEADER_RETRY_AFTER, "soon"),
+            (HEADER_RESET_REQUESTS, ""),
+        ]));
+        assert_eq!(hint, None);
+    }
+
+    #[test]
+    fn hint_is_capped() {
+        let retry = Retry::new(&RetrySetup::default());
+        let wait = retry.get_delay(1, Some(&headers(&[(HEADER_RETRY_AFTER, "86400")])));
+        assert_eq!(wait, retry.max_delay);
+    }
+}
@@ -6,35 +6,36 @@ pub fn scan_folder(setup: &mut Setup, path: &str) {
     let mut list = Vec::<LLamaSetup>::new();
     match fs::read_dir(path) {
         Ok(entries) => {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("gguf") {
-                        if !exists(setup, path.to_str().unwrap()) {
-                            if let Some(base) = path.file_stem().and_then(|s| s.to_str()) {
-                                list.push(LLamaSetup {
-                                    name: get_name(base),
-                                    model: path.to_str().unwrap().to_string(),
-                                    prompt: None,
-                                    history: None,
-                                    temperature: None,
-                                    n_gpu_layers: None,
-                                    tokens: None,
-                                    threads: None,
-                                    top_k: None,
-                                    top_p: None,
-                                });
-                            }
-                        } else {
-                            println!("Model already exists: {}", path.to_str().unwrap());
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("gguf") {
+                    if !exists(setup, path.to_str().unwrap()) {
+                        if let Some(base) = path.file_stem().and_then(|s| s.to_str()) {
+                            list.push(LLamaSetup {
+                                name: get_name(base),
+                                model: path.to_str().unwrap().to_string(),
+                                prompt: None,
+                                history: None,
+                                temperature: None,
+                                n_gpu_layers: None,
+                                tokens: None,
+                                threads: None,
+                                top_k: None,
+                                top_p: None,
+                                context_size: None,
+                            });
                         }
+                    } else {
+                        println!("Model already exists: {}", path.to_str().unwrap());
                     }
                 }
             }
             if !list.is_empty() {
                 println!("{}", serde_json::to_string_pretty(&list).unwrap());
                 setup.add_locals(list);
-                setup.save();
+                if let Err(e) = setup.save() {
+                    log::error!("{}", e);
+                }
             } else {
                 println!("No (new) models found");
             }
@@ -1,14 +1,47 @@
+use crate::error::AirError;
+use crate::ichat::Sampling;
 use crate::path::{get_config_path, FileInfo};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs;
-use std::io::ErrorKind;
 use std::path::Path;
 
 pub(crate) const DEFAULT_MODEL: &str = "gpt-4-1106-preview";
+pub(crate) const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+pub(crate) const DEFAULT_ANTHROPIC_MODEL: &str = "claude-3-opus-20240229";
+const DEFAULT_ANTHROPIC_URL: &str = "https://api.anthropic.com/v1";
+const DEFAULT_ANTHROPIC_MAX_TOKENS: u32 = 4096;
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+const DEFAULT_RETRY_DELAY: u64 = 1000; // ms
+const DEFAULT_RETRY_MAX_DELAY: u64 = 30000; // ms
+const DEFAULT_MAX_STDIN: usize = 1024 * 1024; // bytes
+const DEFAULT_MAX_CONTEXT: usize = 256 * 1024; // bytes
 const EMPTY_KEY: &str = "<enter your openai api key here>";
 const DEFAULT_SYSTEM: &str = "Your are a Linux assistant and a coder.";
 const DEFAULT_EXPIRATION: u32 = 60 * 60 * 24; // 24h
+const DEFAULT_RESERVED_TOKENS: usize = 1024;
+const DEFAULT_CONTEXT_WINDOW: usize = 4096; // tokens
+const DEFAULT_LLAMA_CONTEXT: usize = 512; // llama_cpp_rs default
+const DEFAULT_OLLAMA_CONTEXT: usize = 2048; // ollama default num_ctx
+/// Context windows of known models (longest prefix wins), tokens.
+const CONTEXT_WINDOWS: [(&str, usize); 14] = [
+    ("gpt-4o", 128000),
+    ("gpt-4-turbo", 128000),
+    ("gpt-4-1106", 128000),
+    ("gpt-4-0125", 128000),
+    ("gpt-4-32k", 32768),
+    ("gpt-4", 8192),
+    ("gpt-3.5-turbo", 16385),
+    ("claude", 200000),
+    ("mistral", 32768),
+    ("mixtral", 32768),
+    ("llama3", 8192),
+    ("llama2", 4096),
+    ("gemma", 8192),
+    ("phi3", 4096),
+];
 
 const EX_NAME: &str = "vigogne";
 const EX_MODEL: &str = "/opt/models/vigogne-2-7b-chat.Q4_K_M.gguf";
@@ -20,6 +53,11 @@ const EX_THREADS: i32 = 14;
 const EX_TOP_K: i32 = 90;
 const EX_TOP_P: f32 = 0.8;
 const EX_TEMPERATURE: f32 = 0.2;
+const EX_PERSONAS: [(&str, &str, f32); 3] = [
+    ("reviewer", "You are a senior developer reviewing code : point out bugs, risks and unclear parts, briefly.", 0.2),
+    ("shell", "You are a Linux shell expert, answer with the command line only.", 0.0),
+    ("translator", "Translate the text to english, answer with the translation only.", 0.3),
+];
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LLamaSetup {
@@ -41,6 +79,8 @@ pub struct LLamaSetup {
     pub top_k: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_size: Option<i32>,
 }
 
 impl LLamaSetup {
@@ -50,14 +90,198 @@ impl LLamaSetup {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoteSetup {
+    pub name: String,
+    pub base_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apikey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    /// Usage requested with `stream_options` (default for OpenAI only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_usage: Option<bool>,
+}
+
+impl RemoteSetup {
+    pub fn get_stream_usage(&self) -> bool {
+        return self
+            .stream_usage
+            .unwrap_or(self.base_url == DEFAULT_BASE_URL);
+    }
+}
+
+/// Named system prompt with its own model and sampling (`-p name`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PersonaSetup {
+    pub name: String,
+    pub system: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+}
+
+impl PersonaSetup {
+    pub fn get_sampling(&self) -> Sampling {
+        return Sampling {
+            temperature: self.temperature,
+            top_p: self.top_p,
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AnthropicSetup {
+    pub apikey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl AnthropicSetup {
+    pub fn get_model(&self) -> String {
+        return self
+            .model
+            .clone()
+            .unwrap_or(DEFAULT_ANTHROPIC_MODEL.to_string());
+    }
+
+    pub fn get_base_url(&self) -> String {
+        return self
+            .base_url
+            .clone()
+            .unwrap_or(DEFAULT_ANTHROPIC_URL.to_string());
+    }
+
+    pub fn get_max_tokens(&self) -> u32 {
+        return self.max_tokens.unwrap_or(DEFAULT_ANTHROPIC_MAX_TOKENS);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OllamaSetup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_gpu_layers: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_size: Option<i32>,
+}
+
+impl OllamaSetup {
+    pub fn get_url(&self) -> String {
+        return self
+            .url
+            .clone()
+            .unwrap_or(DEFAULT_OLLAMA_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RetrySetup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_delay: Option<u64>,
+}
+
+impl RetrySetup {
+    pub fn get_attempts(&self) -> u32 {
+        return self.attempts.unwrap_or(DEFAULT_RETRY_ATTEMPTS);
+    }
+
+    pub fn get_delay(&self) -> u64 {
+        return self.delay.unwrap_or(DEFAULT_RETRY_DELAY);
+    }
+
+    pub fn get_max_delay(&self) -> u64 {
+        return self.max_delay.unwrap_or(DEFAULT_RETRY_MAX_DELAY);
+    }
+}
+
+/// USD per million tokens.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Setup {
+    #[serde(default)]
     pub apikey: String,
     pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    /// Usage requested with `stream_options` (default for OpenAI only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_usage: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anthropic: Option<AnthropicSetup>,
     pub system: Option<String>,
     pub markdown: Option<bool>,
     pub expiration: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_stdin: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_context: Option<usize>,
+    /// Context window per model (prefix), tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contexts: Option<HashMap<String, usize>>,
+    /// Tokens kept free for the answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserved_tokens: Option<usize>,
+    /// Condense the expired or overflowing messages instead of dropping them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summarize: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetrySetup>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prices: Option<HashMap<String, Price>>,
     pub local: Option<Vec<LLamaSetup>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub personas: Option<Vec<PersonaSetup>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama: Option<OllamaSetup>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<Vec<RemoteSetup>>,
+}
+
+/// Value of the longest key prefixing the model name.
+fn get_by_prefix<'a, K: AsRef<str>>(
+    values: impl Iterator<Item = (K, &'a usize)>,
+    model: &str,
+) -> Option<usize> {
+    return values
+        .filter(|(prefix, _)| model.starts_with(prefix.as_ref()))
+        .max_by_key(|(prefix, _)| prefix.as_ref().len())
+        .map(|(_, size)| *size);
 }
 
 impl Default for Setup {
@@ -65,42 +289,47 @@ impl Default for Setup {
         Self {
             apikey: EMPTY_KEY.to_string(),
             model: Some(DEFAULT_MODEL.to_string()),
+            base_url: None,
+            headers: None,
+            stream_usage: None,
+            anthropic: None,
             system: Some(DEFAULT_SYSTEM.to_string()),
             markdown: Some(true),
             expiration: Some(DEFAULT_EXPIRATION),
+            max_stdin: None,
+            max_context: None,
+            contexts: None,
+            reserved_tokens: None,
+            summarize: None,
+            retry: None,
+            prices: None,
             local: None,
+            personas: None,
+            ollama: None,
+            remote: None,
         }
     }
 }
 
 #[allow(dead_code)]
 impl Setup {
-    pub fn new() -> Result<Self, std::io::Error> {
+    pub fn new() -> Result<Self, AirError> {
         let config: FileInfo = get_config_path("setup.json");
         if !config.exists {
             // match Setup::write(&config) {
             //     Ok(_) => (),
             //     Err(e) => log::error!("{}", e),
             // };
-            return Err(std::io::Error::new(
-                ErrorKind::NotFound,
-                format!(
-                    "Setup file {} does not exists !\nSetup example:\n{}\n",
-                    &config.path,
-                    &Setup::get_example()?
-                ),
-            ));
+            return Err(AirError::Config(format!(
+                "Setup file {} does not exists !\nSetup example:\n{}\n",
+                &config.path,
+                &Setup::get_example()?
+            )));
         }
 
         let contents: String = fs::read_to_string(config.path.clone())?;
-        let setup: Setup = serde_json::from_str(&contents)?;
-
-        if setup.apikey.is_empty() || setup.apikey == EMPTY_KEY {
-            return Err(std::io::Error::new(
-                ErrorKind::NotFound,
-                format!("Edit setup file {}, and set your api key !", &config.path),
-            ));
-        }
+        let setup: Setup = serde_json::from_str(&contents)
+            .map_err(|e| AirError::Config(format!("{} : {}", &config.path, e)))?;
         return Ok(setup);
     }
 
@@ -112,16 +341,120 @@ impl Setup {
         return self.expiration.unwrap_or(DEFAULT_EXPIRATION);
     }
 
+    pub fn get_summarize(&self) -> bool {
+        return self.summarize.unwrap_or(false);
+    }
+
+    pub fn get_max_stdin(&self) -> usize {
+        return self.max_stdin.unwrap_or(DEFAULT_MAX_STDIN);
+    }
+
+    pub fn get_max_context(&self) -> usize {
+        return self.max_context.unwrap_or(DEFAULT_MAX_CONTEXT);
+    }
+
+    /// Context window of a model : the `contexts` of the setup, the context
+    /// size of local models, then the known models.
+    pub fn get_context_window(&self, backend: &str, model: &str) -> usize {
+        if let Some(size) = self
+            .contexts
+            .as_ref()
+            .and_then(|c| get_by_prefix(c.iter(), model))
+        {
+            return size;
+        }
+        return match backend {
+            "llama" => self
+                .local
+                .iter()
+                .flatten()
+                .find(|l| l.name == model)
+                .and_then(|l| l.context_size)
+                .map(|size| size as usize)
+                .unwrap_or(DEFAULT_LLAMA_CONTEXT),
+            "ollama" => self
+                .ollama
+                .as_ref()
+                .and_then(|o| o.context_size)
+                .map(|size| size as usize)
+                .unwrap_or(DEFAULT_OLLAMA_CONTEXT),
+            _ => get_by_prefix(CONTEXT_WINDOWS.iter().map(|(m, s)| (*m, s)), model)
+                .unwrap_or(DEFAULT_CONTEXT_WINDOW),
+        };
+    }
+
+    /// Tokens kept free for the answer, `max_tokens` for anthropic.
+    pub fn get_reserved_tokens(&self, backend: &str) -> usize {
+        if backend == "anthropic" {
+            if let Some(anthropic) = &self.anthropic {
+                return anthropic.get_max_tokens() as usize;
+            }
+        }
+        return self.reserved_tokens.unwrap_or(DEFAULT_RESERVED_TOKENS);
+    }
+
+    pub fn get_retry(&self) -> RetrySetup {
+        return self.retry.clone().unwrap_or_default();
+    }
+
+    pub fn get_price(&self, model: &str) -> Option<&Price> {
+        return self.prices.as_ref()?.get(model);
+    }
+
     pub fn get_model(&self) -> String {
         return self.model.clone().unwrap_or(DEFAULT_MODEL.to_string());
     }
 
+    pub fn get_base_url(&self) -> String {
+        return self
+            .base_url
+            .clone()
+            .unwrap_or(DEFAULT_BASE_URL.to_string());
+    }
+
+    /// OpenAI key, empty when not set (or still the one of the example).
+    pub fn get_apikey(&self) -> String {
+        if self.apikey == EMPTY_KEY {
+            return String::new();
+        }
+        return self.apikey.clone();
+    }
+
+    /// A key is only mandatory for the official endpoint.
+    pub fn check_apikey(&self) -> Result<(), AirError> {
+        if self.get_base_url() == DEFAULT_BASE_URL && self.get_apikey().is_empty() {
+            return Err(AirError::Config(format!(
+                "Edit setup file {}, and set your api key !",
+                get_config_path("setup.json").path
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn get_stream_usage(&self) -> bool {
+        return self
+            .stream_usage
+            .unwrap_or(self.get_base_url() == DEFAULT_BASE_URL);
+    }
+
+    pub fn get_headers(&self) -> HashMap<String, String> {
+        return self.headers.clone().unwrap_or_default();
+    }
+
+    pub fn get_persona(&self, name: &str) -> Option<&PersonaSetup> {
+        return self.personas.as_ref()?.iter().find(|p| p.name.eq(name));
+    }
+
+    pub fn get_remote(&self, name: &str) -> Option<&RemoteSetup> {
+        return self.remote.as_ref()?.iter().find(|r| r.name.eq(name));
+    }
+
     pub fn get_system(&self) -> String {
         return self.system.clone().unwrap_or(DEFAULT_SYSTEM.to_string());
     }
 
-    fn get_example() -> Result<String, serde_json::error::Error> {
-        return Ok(serde_json::to_string_pretty(&Setup {
+    pub(crate) fn get_example() -> Result<String, serde_json::error::Error> {
+        return serde_json::to_string_pretty(&Setup {
             local: Some(vec![LLamaSetup {
                 name: EX_NAME.into(),
                 model: EX_MODEL.into(),
@@ -133,9 +466,22 @@ impl Setup {
                 top_k: Some(EX_TOP_K),
                 top_p: Some(EX_TOP_P),
                 temperature: Some(EX_TEMPERATURE),
+                context_size: None,
             }]),
+            personas: Some(
+                EX_PERSONAS
+                    .iter()
+                    .map(|(name, system, temperature)| PersonaSetup {
+                        name: name.to_string(),
+                        system: system.to_string(),
+                        model: None,
+                        temperature: Some(*temperature),
+                        top_p: None,
+                    })
+                    .collect(),
+            ),
             ..Default::default()
-        })?);
+        });
     }
 
     // #[allow(dead_code)]
@@ -147,9 +493,19 @@ impl Setup {
     pub fn display(&self) {
         termimad::print_inline(&format!("*APIKEY*     => `{}`\n", self.apikey));
         termimad::print_inline(&format!("*MODEL*      => `{}`\n", self.get_model()));
+        termimad::print_inline(&format!("*BASE URL*   => `{}`\n", self.get_base_url()));
         termimad::print_inline(&format!("*SYSTEM*     => `{}`\n", self.get_system()));
         termimad::print_inline(&format!("*MARKDOWN*   => `{}`\n", self.get_markdown()));
         termimad::print_inline(&format!("*EXPIRATION* => `{}`\n", self.get_expiration()));
+        termimad::print_inline(&format!("*MAX STDIN*  => `{}`\n", self.get_max_stdin()));
+        termimad::print_inline(&format!("*MAX CONTEXT* => `{}`\n", self.get_max_context()));
+        termimad::print_inline(&format!(
+            "*RESERVED*   => `{}`\n",
+            self.reserved_tokens.unwrap_or(DEFAULT_RESERVED_TOKENS)
+        ));
+        termimad::print_inline(&format!("*CONTEXTS*   => `{:?}`\n", self.contexts));
+        termimad::print_inline(&format!("*SUMMARIZE*  => `{}`\n", self.get_summarize()));
+        termimad::print_inline(&format!("*RETRY*      => `{:?}`\n", self.get_retry()));
 
         if let Some(local) = &self.local {
             for (i, llama) in local.iter().enumerate() {
@@ -159,19 +515,54 @@ impl Setup {
                 termimad::print_inline(&format!("- *PROMPT*   => `{:?}`\n", llama.prompt));
             }
         }
+        if let Some(ollama) = &self.ollama {
+            termimad::print_inline("# OLLAMA\n");
+            termimad::print_inline(&format!("- *URL*      => `{}`\n", ollama.get_url()));
+        }
+        if let Some(anthropic) = &self.anthropic {
+            termimad::print_inline("# ANTHROPIC\n");
+            termimad::print_inline(&format!("- *MODEL*    => `{}`\n", anthropic.get_model()));
+            termimad::print_inline(&format!("- *BASE URL* => `{}`\n", anthropic.get_base_url()));
+        }
+        if let Some(remote) = &self.remote {
+            for (i, r) in remote.iter().enumerate() {
+                termimad::print_inline(&format!("# REMOTE {}\n", i));
+                termimad::print_inline(&format!("- *NAME*     => `{:?}`\n", r.name));
+                termimad::print_inline(&format!("- *BASE URL* => `{:?}`\n", r.base_url));
+                termimad::print_inline(&format!("- *MODEL*    => `{:?}`\n", r.model));
+            }
+        }
+        if let Some(personas) = &self.personas {
+            for (i, p) in personas.iter().enumerate() {
+                termimad::print_inline(&format!("# PERSONA {}\n", i));
+                termimad::print_inline(&format!("- *NAME*     => `{:?}`\n", p.name));
+                termimad::print_inline(&format!("- *SYSTEM*   => `{:?}`\n", p.system));
+                termimad::print_inline(&format!("- *MODEL*    => `{:?}`\n", p.model));
+            }
+        }
         termimad::print_inline("___\n");
     }
 
     pub(crate) fn add_locals(&mut self, list: Vec<LLamaSetup>) {
-        if self.local.is_none() {
-            self.local = Some(list);
-        } else {
-            self.local.as_mut().unwrap().extend(list);
+        match self.local.as_mut() {
+            Some(local) => local.extend(list),
+            None => self.local = Some(list),
+        }
+    }
+
+    /// Remove a local model from the setup (the file is kept).
+    pub(crate) fn remove_local(&mut self, name: &str) -> bool {
+        if let Some(local) = &mut self.local {
+            let count = local.len();
+            local.retain(|l| l.name != name);
+            return local.len() != count;
         }
+        return false;
     }
 
-    pub(crate) fn save(&self) {
+    pub(crate) fn save(&self) -> Result<(), AirError> {
         let config: FileInfo = get_config_path("setup.json");
-        let _ = fs::write(&config.path, serde_json::to_string_pretty(&self).unwrap());
+        fs::write(&config.path, serde_json::to_string_pretty(&self)?)?;
+        Ok(())
     }
 }
@@ -0,0 +1,174 @@
+use crate::{
+    ask, error::AirError, history::History, ichat::IChat, options::CommandLine, output::Output,
+    setup::Setup, tools::read_tty,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+use rustyline::DefaultEditor;
+use serde::Deserialize;
+use std::io::Write;
+use std::sync::OnceLock;
+use termimad::crossterm::style::Stylize;
+
+const DEFAULT_SHELL: &str = "sh";
+const SYSTEM: &str = "You translate requests into exactly one shell command for {os} and the {shell} shell. \
+Answer only with a json object : {\"command\": \"<the command>\", \"explanation\": \"<one short sentence>\"}. \
+Prefer a single line, use pipes or && to chain steps, never add anything outside the json object.";
+/// Patterns worth a second look before running the command.
+const DANGEROUS: [(&str, &str); 8] = [
+    (
+        r"\brm\s+(-\S+\s+)*(-[a-zA-Z]*[rR]|--recursive)",
+        "recursive removal",
+    ),
+    (r"--no-preserve-root", "removal of /"),
+    (r"\bdd\b", "raw disk write (dd)"),
+    (r"\bmkfs(\.\w+)?\b", "filesystem creation (mkfs)"),
+    (r">\s*/dev/(sd|nvme|hd|mmcblk)", "write to a block device"),
+    (r":\(\)\s*\{\s*:\|:&\s*\};:", "fork bomb"),
+    (
+        r"\bchmod\s+(-\S+\s+)*777\s+/",
+        "world writable system files",
+    ),
+    (r"\b(shred|wipefs)\b", "data destruction"),
+];
+
+#[derive(Deserialize, Debug)]
+struct Proposal {
+    command: String,
+    #[serde(default)]
+    explanation: String,
+}
+
+fn get_shell() -> String {
+    let shell = std::env::var("SHELL").unwrap_or(DEFAULT_SHELL.to_string());
+    return shell
+        .rsplit('/')
+        .next()
+        .unwrap_or(DEFAULT_SHELL)
+        .to_string();
+}
+
+/// The json object of the answer, a fenced block (or the raw answer) when the
+/// model didn't comply.
+fn parse(answer: &str) -> Proposal {
+    if let (Some(start), Some(end)) = (answer.find('{'), answer.rfind('}')) {
+        if start < end {
+            if let Ok(proposal) = serde_json::from_str::<Proposal>(&answer[start..=end]) {
+                return proposal;
+            }
+        }
+    }
+    let fenced = Regex::new(r"(?s)```[a-z]*\n(.*?)\n?```").unwrap();
+    let command = match fenced.captures(answer) {
+        Some(caps) => caps[1].trim().to_string(),
+        None => answer.trim().to_string(),
+    };
+    return Proposal {
+        command,
+        explanation: String::new(),
+    };
+}
+
+pub fn get_dangers(command: &str) -> Vec<&'static str> {
+    static PATTERNS: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        DANGEROUS
+            .iter()
+            .map(|(pattern, reason)| (Regex::new(pattern).unwrap(), *reason))
+            .collect()
+    });
+    return patterns
+        .iter()
+        .filter(|(regex, _)| regex.is_match(command))
+        .map(|(_, reason)| *reason)
+        .collect();
+}
+
+/// Copy through the terminal (OSC 52), works over ssh too.
+fn copy(command: &str) {
+    print!("\x1b]52;c;{}\x07", STANDARD.encode(command));
+    let _ = std::io::stdout().flush();
+    println!("Copied to the clipboard.");
+}
+
+fn execute(shell: &str, command: &str) -> Result<Option<i32>, AirError> {
+    let status = std::process::Command::new(shell)
+        .arg("-c")
+        .arg(command)
+        .status()?;
+    return Ok(status.code());
+}
+
+/// Ask for a command, then execute, edit, copy or cancel it. A command which
+/// fails is an error, its own exit code is only reported.
+pub async fn run(
+    ichat: &mut Box<dyn IChat>,
+    setup: &Setup,
+    options: &CommandLine,
+    history: &mut History,
+    prompt: &str,
+) -> Result<(), AirError> {
+    let shell = get_shell();
+    let instructions = SYSTEM
+        .replace("{os}", std::env::consts::OS)
+        .replace("{shell}", &shell);
+    // the instructions come last, the answer format must win
+    let system = match options.system.as_deref().filter(|s| !s.trim().is_empty()) {
+        Some(system) => format!("{}\n\n{}", system, instructions),
+        None => instructions,
+    };
+    ichat.set_system(system.clone());
+    if options.output.is_json() {
+        // scripts get the proposal only
+        ask(ichat, setup, options, history, &system, prompt).await?;
+        return Ok(());
+    }
+    // the json answer is not streamed
+    ichat.set_output(Output::Json);
+    let proposal = parse(&ask(ichat, setup, options, history, &system, prompt).await?);
+    let mut command = proposal.command;
+    let explanation = proposal.explanation;
+    if command.is_empty() {
+        return Err(AirError::Backend("No command proposed.".to_string()));
+    }
+
+    loop {
+        println!("\n  {}", Stylize::bold(command.as_str()));
+        if !explanation.is_empty() {
+            println!("  {}", Stylize::dark_grey(explanation.as_str()));
+        }
+        for danger in get_dangers(&command) {
+            println!("  {} {}", Stylize::red("⚠ Dangerous :"), danger);
+        }
+        print!("\n[e]xecute, e[d]it, [c]opy, cancel ? ");
+        let _ = std::io::stdout().flush();
+
+        let choice = read_tty().unwrap_or_default();
+        match choice.trim().to_lowercase().as_str() {
+            "e" | "execute" => {
+                let exit_code = execute(&shell, &command)?;
+                history.set_command(&command, exit_code);
+                history.save()?;
+                return match exit_code {
+                    Some(0) => Ok(()),
+                    Some(code) => Err(AirError::CommandFailed(format!("exit code {}", code))),
+                    None => Err(AirError::CommandFailed("killed by a signal".to_string())),
+                };
+            }
+            "d" | "edit" => {
+                let mut editor =
+                    DefaultEditor::new().map_err(|e| AirError::Io(std::io::Error::other(e)))?;
+                match editor.readline_with_initial("$ ", (&command, "")) {
+                    Ok(edited) if !edited.trim().is_empty() => command = edited.trim().to_string(),
+                    Ok(_) => (),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+            "c" | "copy" => {
+                copy(&command);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+// Incremental Server-Sent Events decoder
+// https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation
+
+const DEFAULT_EVENT: &str = "message";
+const BOM: &str = "\u{feff}";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    /// `event:` field, `message` when the server didn't send one.
+    pub event: String,
+    /// `data:` fields joined with `\n`.
+    pub data: String,
+    /// Last event id seen on the stream.
+    pub id: Option<String>,
+    /// Reconnection time (ms) requested by the server.
+    pub retry: Option<u64>,
+}
+
+impl SseEvent {
+    /// OpenAI style end of stream marker.
+    pub fn is_done(&self) -> bool {
+        return self.data == "[DONE]";
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    started: bool,
+    skip_lf: bool,
+    event: Option<String>,
+    data: String,
+    has_data: bool,
+    id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        return SseDecoder::default();
+    }
+
+    /// Feed raw bytes as they come from the network, returns every event
+    /// completed by this chunk. Lines may be split anywhere, including in
+    /// the middle of a `\r\n` pair or of an utf-8 sequence.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = vec![];
+        for &c in bytes {
+            if self.skip_lf {
+                self.skip_lf = false;
+                if c == b'\n' {
+                    continue;
+                }
+            }
+            match c {
+                b'\r' | b'\n' => {
+                    self.skip_lf = c == b'\r';
+                    let line = std::mem::take(&mut self.buffer);
+                    if let Some(event) = self.process_line(&line) {
+                        events.push(event);
+                    }
+                }
+                _ => self.buffer.push(c),
+            }
+        }
+        return events;
+    }
+
+    /// End of stream : flush a trailing line and dispatch a pending event,
+    /// some servers close the connection without the final blank line.
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let mut events = vec![];
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        if let Some(event) = self.dispatch() {
+            events.push(event);
+        }
+        return events;
+    }
+
+    fn process_line(&mut self, bytes: &[u8]) -> Option<SseEvent> {
+        let decoded = String::from_utf8_lossy(bytes);
+        let mut line: &str = &decoded;
+        if !self.started {
+            self.started = true;
+            line = line.strip_prefix(BOM).unwrap_or(line);
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // comment / keep-alive
+            return None;
+        }
+
+        let (field, value) = match line.find(':') {
+            Some(pos) => {
+                let value = &line[pos + 1..];
+                (&line[..pos], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "id" => {
+                if !value.contains('\0') {
+                    self.id = Some(value.to_string());
+                }
+            }
+            "retry" => {
+                if let Ok(retry) = value.parse::<u64>() {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => log::debug!("SSE: ignoring field '{}'", field),
+        }
+        return None;
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if !self.has_data {
+            return None;
+        }
+        self.has_data = false;
+        return Some(SseEvent {
+            event: event
+                .filter(|e| !e.is_empty())
+                .unwrap_or(DEFAULT_EVENT.to_string()),
+            data: std::mem::take(&mut self.data),
+            id: self.id.clone(),
+            retry: self.retry,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events = vec![];
+        for chunk in chunks {
+            events.extend(decoder.push(chunk));
+        }
+        events.extend(decoder.finish());
+        return events;
+    }
+
+    #[test]
+    fn event_and_multiline_data() {
+        let events = decode(&[b"event: delta\ndata: a\ndata: b\n\ndata: c\n\n"]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "delta");
+        assert_eq!(events[0].data, "a\nb");
+        assert_eq!(events[1].event, DEFAULT_EVENT);
+        assert_eq!(events[1].data, "c");
+    }
+
+    #[test]
+    fn crlf_split_across_chunks() {
+        // the \n after a \r ends the same line, it's not a blank line
+        let events = decode(&[b"data: a\r", b"\ndata: b\r\n\r", b"\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "a\nb");
+    }
+
+    #[test]
+    fn cr_only_line_endings() {
+        let events = decode(&[b"data: a\r\rdata: b\r\r"]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].data, "b");
+    }
+
+    #[test]
+    fn utf8_split_across_chunks() {
+        let bytes = "data: caf\u{e9} \u{1f600}\n\n".as_bytes();
+        let chunks: Vec<&[u8]> = vec![&bytes[..10], &bytes[10..13], &bytes[13..]];
+        let events = decode(&chunks);
+        assert_eq!(events[0].data, "caf\u{e9} \u{1f600}");
+    }
+
+    #[test]
+    fn id_and_retry_fields() {
+        let events = decode(&[
+            b"id: 1\nretry: 1500\ndata: a\n\ndata: b\n\nid: 2\0\nretry: soon\ndata: c\n\n",
+        ]);
+        assert_eq!(events[0].id.as_deref(), Some("1"));
+        assert_eq!(events[0].retry, Some(1500));
+        // both are kept for the next events
+        assert_eq!(events[1].id.as_deref(), Some("1"));
+        assert_eq!(events[1].retry, Some(1500));
+        // an id with a null and an invalid retry are ignored
+        assert_eq!(events[2].id.as_deref(), Some("1"));
+        assert_eq!(events[2].retry, Some(1500));
+    }
+
+    #[test]
+    fn comments_bom_and_field_without_value() {
+        let events = decode(&["\u{feff}: keep-alive\ndata\n\ndata:no space\n\n".as_bytes()]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, "");
+        assert_eq!(events[1].data, "no space");
+    }
+
+    #[test]
+    fn event_without_data_is_not_dispatched() {
+        let events = decode(&[b"event: ping\n\ndata: a\n\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, DEFAULT_EVENT);
+    }
+
+    #[test]
+    fn finish_without_trailing_blank_line() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: [DONE]").is_empty());
+        let events = decoder.finish();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_done());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{context::get_fence, error::AirError};
+use std::io::Read;
+
+/// Time given to a pipe which isn't explicitly read (ms).
+const STDIN_WAIT: i32 = 1000;
+
+/// Piped input, `None` when stdin is a terminal. Unless `requested`, a pipe
+/// which sends nothing (cron, ssh ...) is ignored after `STDIN_WAIT`.
+pub fn read_stdin(limit: usize, requested: bool) -> Result<Option<String>, AirError> {
+    if is_terminal() {
+        return Ok(None);
+    }
+    if !requested && !is_ready(STDIN_WAIT) {
+        log::debug!("Nothing piped after {}ms, stdin ignored", STDIN_WAIT);
+        return Ok(None);
+    }
+    let mut bytes = vec![];
+    // one more byte to detect an input over the limit
+    std::io::stdin()
+        .lock()
+        .take(limit as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() > limit {
+        return Err(AirError::Usage(format!(
+            "Piped input is larger than {} bytes (see max_stdin in setup)",
+            limit
+        )));
+    }
+    let input = String::from_utf8_lossy(&bytes).trim_end().to_string();
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    return Ok(Some(input));
+}
+
+/// Data (or the end of input) is available on stdin.
+fn is_ready(timeout: i32) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    return unsafe { libc::poll(&mut fd, 1, timeout) } > 0;
+}
+
+pub fn is_terminal() -> bool {
+    return unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+}
+
+/// Prompt sent (and recorded in history) : the piped input is either the
+/// prompt itself, or a fenced context block following the prompt.
+pub fn combine(prompt: &str, input: &str, as_prompt: bool) -> String {
+    if prompt.is_empty() {
+        return input.to_string();
+    }
+    if as_prompt {
+        return format!("{}\n\n{}", prompt, input);
+    }
+    let fence = get_fence(input);
+    return format!("{}\n\n{}\n{}\n{}", prompt, fence, input, fence);
+}
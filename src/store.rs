@@ -0,0 +1,87 @@
+use chrono::Local;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+// history files are append-only journals (one json record per line), written
+// under an advisory lock so that several `air` running at once don't lose
+// each other's messages, and rewritten with a rename so a crash leaves either
+// the old or the new file
+
+/// Exclusive `flock` on a lock file, released on drop (or when the process
+/// dies).
+pub struct Lock {
+    file: File,
+}
+
+impl Lock {
+    /// Block until the lock is acquired, the lock file is never replaced so
+    /// its inode stays the same for every process.
+    pub fn acquire(path: &Path) -> Result<Self, Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        return Ok(Lock { file });
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+/// Append the lines in one write, flushed to the disk.
+pub fn append(path: &Path, lines: &[String]) -> Result<(), Error> {
+    let mut buffer = String::new();
+    for line in lines {
+        buffer.push_str(line);
+        buffer.push('\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(buffer.as_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Replace the file content : write a temporary file next to it then rename.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), Error> {
+    let temporary = with_suffix(path, "tmp");
+    {
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&temporary, path)?;
+    if let Some(parent) = path.parent() {
+        // persist the rename itself
+        if let Ok(directory) = File::open(parent) {
+            let _ = directory.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Copy a file aside (`<file>.<kind>-<date>`), returns the copy.
+pub fn backup(path: &Path, kind: &str) -> Result<PathBuf, Error> {
+    let date = Local::now().format("%Y%m%d-%H%M%S");
+    let copy = with_suffix(path, &format!("{}-{}", kind, date));
+    fs::copy(path, &copy)?;
+    return Ok(copy);
+}
+
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    return PathBuf::from(name);
+}
@@ -0,0 +1,87 @@
+use crate::{
+    error::AirError, history::History, ichat::IChat, options::CommandLine, output::Output,
+    setup::Setup, usage, window,
+};
+
+// the messages which expire or don't fit in the context window anymore are
+// condensed by the model into a summary, which then replaces them
+
+const SUMMARY_PROMPT: &str = "Summarize the conversation below so that it can be continued \
+    without it : keep the facts, decisions, names, figures, code and open questions, drop \
+    the small talk. Merge the previous summary if there is one. Answer with the summary only, \
+    as concisely as possible.";
+
+/// Summarize the expired messages and the ones which won't fit with the
+/// prompt, the summary is saved with the history (and not printed).
+pub async fn update(
+    ichat: &mut Box<dyn IChat>,
+    setup: &Setup,
+    options: &CommandLine,
+    history: &mut History,
+) -> Result<(), AirError> {
+    let name = ichat.get_name().to_string();
+    let model = ichat.get_model();
+    let window = setup.get_context_window(&name, &model);
+    let reserved = setup.get_reserved_tokens(&name);
+    let (_, trim) = window::fit(
+        history.get_completions(),
+        options.system.as_deref().unwrap_or(""),
+        &options.prompt,
+        window,
+        reserved,
+    );
+    // the summary is the oldest turn, it's replaced anyway
+    let summarized = history.get_summary().is_some() as usize;
+    let overflow = trim.dropped.saturating_sub(summarized);
+    let messages = history.get_to_summarize(overflow);
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    // the most recent of them if they don't fit either
+    let previous = history
+        .get_summary()
+        .map(|s| format!("Previous summary :\n\n{}", s.text))
+        .unwrap_or_default();
+    let instructions = format!("{}\n\n{}", SUMMARY_PROMPT, previous);
+    let (kept, _) = window::fit(
+        History::to_completions(&messages),
+        &instructions,
+        "",
+        window,
+        reserved,
+    );
+    let prompt = format!(
+        "{}\n\n# Conversation\n\n{}",
+        instructions,
+        History::format_markdown(&kept)
+    );
+
+    // announced, it's one more (paid) request
+    log::warn!(
+        "Summarizing {} older message(s) with {}/{} ...",
+        messages.len(),
+        name,
+        model
+    );
+    ichat.set_output(Output::Json);
+    let text = ichat.chat(prompt, None).await?;
+    if text.trim().is_empty() {
+        return Err(AirError::Backend("Empty summary.".to_string()));
+    }
+    if let Some(usage) = ichat.get_usage() {
+        let cost = usage::get_cost(&usage, setup.get_price(&model));
+        if let Err(e) = usage::record(&name, &model, &usage, cost) {
+            log::error!("{}", e);
+        }
+    }
+
+    history.set_summary(text.trim(), &messages);
+    history.save()?;
+    log::info!(
+        "Summary    : ~{} tokens for {} message(s)",
+        usage::estimate_tokens(text.trim()),
+        history.get_summary().map(|s| s.messages).unwrap_or(0)
+    );
+    return Ok(());
+}
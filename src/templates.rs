@@ -0,0 +1,195 @@
+use crate::{
+    error::AirError,
+    path::{get_config_directory, is_valid_name},
+};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TEMPLATES_DIRECTORY: &str = "templates";
+const PLACEHOLDER: &str = r"\{\{\s*([A-Za-z0-9_-]+)\s*\}\}";
+/// `--var name=-` reads the variable from stdin.
+const STDIN_VALUE: &str = "-";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Variable {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// `templates/<name>.json`, a prompt with `{{var}}` placeholders.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Template {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub template: String,
+    #[serde(default)]
+    pub variables: BTreeMap<String, Variable>,
+}
+
+impl Template {
+    pub fn load(name: &str) -> Result<Self, AirError> {
+        if !is_valid_name(name) {
+            return Err(AirError::Usage(format!(
+                "Invalid template name '{}' (letters, digits, '-', '_' and '.')",
+                name
+            )));
+        }
+        let path = get_directory().join(format!("{}.json", name));
+        if !path.is_file() {
+            return Err(AirError::Usage(format!(
+                "Can't find template '{}' in {}",
+                name,
+                get_directory().display()
+            )));
+        }
+        let contents = fs::read_to_string(&path)?;
+        return serde_json::from_str(&contents)
+            .map_err(|e| AirError::Config(format!("{} : {}", path.display(), e)));
+    }
+
+    /// Placeholders of the template, in order of appearance.
+    pub fn get_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for caps in Regex::new(PLACEHOLDER)
+            .unwrap()
+            .captures_iter(&self.template)
+        {
+            let name = caps[1].to_string();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        return names;
+    }
+
+    /// Replace the placeholders with `--var name=value` values (`@path`
+    /// loads a file, `-` the piped stdin) or their default.
+    pub fn render(&self, vars: &[String], stdin: &mut Option<String>) -> Result<String, AirError> {
+        let mut values: HashMap<String, String> = HashMap::new();
+        for var in vars {
+            let (name, value) = match var.split_once('=') {
+                Some((name, value)) => (name.trim(), value),
+                None => {
+                    return Err(AirError::Usage(format!(
+                        "Invalid variable '{}', expected name=value",
+                        var
+                    )))
+                }
+            };
+            values.insert(name.to_string(), get_value(name, value, stdin)?);
+        }
+
+        let mut missing = vec![];
+        for name in self.get_names() {
+            if values.contains_key(&name) {
+                continue;
+            }
+            let variable = self.variables.get(&name);
+            match variable.and_then(|v| v.default.clone()) {
+                Some(default) => {
+                    values.insert(name, default);
+                }
+                None => missing.push(match variable.and_then(|v| v.description.as_ref()) {
+                    Some(description) => format!("  {} : {}", name, description),
+                    None => format!("  {}", name),
+                }),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(AirError::Usage(format!(
+                "Missing template variables (--var name=value) :\n{}",
+                missing.join("\n")
+            )));
+        }
+
+        let rendered = Regex::new(PLACEHOLDER)
+            .unwrap()
+            .replace_all(&self.template, |caps: &Captures| values[&caps[1]].clone());
+        return Ok(rendered.trim().to_string());
+    }
+}
+
+/// A `--var name=-` takes the piped input.
+pub fn reads_stdin(vars: &[String]) -> bool {
+    return vars
+        .iter()
+        .any(|var| var.split_once('=').map(|(_, value)| value) == Some(STDIN_VALUE));
+}
+
+fn get_value(name: &str, value: &str, stdin: &mut Option<String>) -> Result<String, AirError> {
+    if value == STDIN_VALUE {
+        return stdin.take().ok_or(AirError::Usage(format!(
+            "Variable '{}' reads stdin, but nothing is piped",
+            name
+        )));
+    }
+    if let Some(path) = value.strip_prefix('@') {
+        return fs::read_to_string(path)
+            .map(|content| content.trim_end().to_string())
+            .map_err(|e| {
+                AirError::Usage(format!("Variable '{}', can't read {} : {}", name, path, e))
+            });
+    }
+    return Ok(value.to_string());
+}
+
+fn get_directory() -> PathBuf {
+    return Path::new(&get_config_directory()).join(TEMPLATES_DIRECTORY);
+}
+
+/// Template names, sorted.
+pub fn get_template_names() -> Result<Vec<String>, AirError> {
+    let directory = get_directory();
+    fs::create_dir_all(&directory)?;
+
+    let mut names: Vec<String> = vec![];
+    for entry in fs::read_dir(&directory)? {
+        let path = entry?.path();
+        if path.extension().map(|e| e == "json").unwrap_or(false) {
+            if let Some(name) = path.file_stem() {
+                names.push(name.to_string_lossy().to_string());
+            }
+        }
+    }
+    names.sort();
+    return Ok(names);
+}
+
+/// `air templates list`
+pub fn list() -> Result<(), AirError> {
+    let names = get_template_names()?;
+    if names.is_empty() {
+        println!("No templates in {}", get_directory().display());
+        return Ok(());
+    }
+    for name in names {
+        let template = match Template::load(&name) {
+            Ok(template) => template,
+            Err(e) => {
+                log::warn!("{}", e);
+                continue;
+            }
+        };
+        println!(
+            "{} : {}",
+            name,
+            template.description.as_deref().unwrap_or("")
+        );
+        for var in template.get_names() {
+            let variable = template.variables.get(&var);
+            let description = variable
+                .and_then(|v| v.description.as_deref())
+                .unwrap_or("");
+            match variable.and_then(|v| v.default.as_ref()) {
+                Some(default) => println!("    {:<12} {} (default: {})", var, description, default),
+                None => println!("    {:<12} {}", var, description),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use crate::ichat::{ToolCall, ToolDefinition};
+use async_trait::async_trait;
+use reqwest::{redirect, Url};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use termimad::crossterm::style::Stylize;
+
+/// Tool outputs are truncated to keep the context small.
+const MAX_OUTPUT: usize = 32 * 1024;
+/// Model/tool round trips before giving up.
+pub const MAX_ROUNDS: usize = 10;
+const LOCALHOSTS: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+const MAX_REDIRECTS: usize = 10;
+
+fn is_local(url: &Url) -> bool {
+    let host = url
+        .host_str()
+        .unwrap_or("")
+        .trim_matches(|c| c == '[' || c == ']');
+    return LOCALHOSTS.contains(&host);
+}
+
+#[async_trait]
+pub trait ITool: Send + Sync {
+    fn definition(&self) -> ToolDefinition;
+    async fn call(&self, arguments: &Value) -> Result<String, String>;
+}
+
+/// Local tools the model may call, every call is confirmed by the user.
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn ITool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        return ToolRegistry {
+            tools: vec![
+                Box::new(ReadFile),
+                Box::new(ListDirectory),
+                Box::new(RunCommand),
+                Box::new(HttpGet),
+            ],
+        };
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        return self.tools.iter().map(|t| t.definition()).collect();
+    }
+
+    /// Run a call requested by the model, errors and refusals are returned
+    /// as text so the model can react to them.
+    pub async fn call(&self, call: &ToolCall) -> String {
+        let name = &call.function.name;
+        let tool = match self.tools.iter().find(|t| t.definition().name == *name) {
+            Some(tool) => tool,
+            None => return format!("Error: unknown tool '{}'", name),
+        };
+        let arguments: Value = match serde_json::from_str(&call.function.arguments) {
+            Ok(arguments) => arguments,
+            Err(e) => return format!("Error: invalid arguments ({})", e),
+        };
+
+        if !confirm(name, &arguments) {
+            return "Error: the user refused to run this tool".to_string();
+        }
+        let result = match tool.call(&arguments).await {
+            Ok(output) => output,
+            Err(e) => format!("Error: {}", e),
+        };
+        log::debug!("{} => {}", name, result);
+        return truncate(result);
+    }
+}
+
+/// Ask on the terminal, stdin may be used for something else.
+fn confirm(name: &str, arguments: &Value) -> bool {
+    eprint!(
+        "\n{} {} {} [y/N] ",
+        Stylize::yellow("⚙ Run tool"),
+        Stylize::bold(name),
+        arguments
+    );
+    let _ = std::io::stderr().flush();
+
+    return match read_tty() {
+        Some(answer) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        None => false,
+    };
+}
+
+/// Read a line on the terminal, stdin when there is none.
+pub fn read_tty() -> Option<String> {
+    let mut answer = String::new();
+    let read = match fs::File::open("/dev/tty") {
+        Ok(tty) => BufReader::new(tty).read_line(&mut answer),
+        Err(_) => std::io::stdin().lock().read_line(&mut answer),
+    };
+    return read.ok().map(|_| answer);
+}
+
+fn truncate(mut output: String) -> String {
+    if output.len() > MAX_OUTPUT {
+        let mut end = MAX_OUTPUT;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.truncate(end);
+        output.push_str("\n[truncated]");
+    }
+    return output;
+}
+
+fn get_string<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, String> {
+    return arguments[name]
+        .as_str()
+        .ok_or(format!("missing '{}' argument", name));
+}
+
+fn path_schema(description: &str) -> Value {
+    return json!({
+        "type": "object",
+        "properties": {
+            "path": { "type": "string", "description": description }
+        },
+        "required": ["path"]
+    });
+}
+
+struct ReadFile;
+
+#[async_trait]
+impl ITool for ReadFile {
+    fn definition(&self) -> ToolDefinition {
+        return ToolDefinition {
+            name: "read_file".to_string(),
+            description: "Read a text file of the local machine".to_string(),
+            parameters: path_schema("Path of the file"),
+        };
+    }
+
+    async fn call(&self, arguments: &Value) -> Result<String, String> {
+        let bytes = fs::read(get_string(arguments, "path")?).map_err(|e| e.to_string())?;
+        return Ok(String::from_utf8_lossy(&bytes).to_string());
+    }
+}
+
+struct ListDirectory;
+
+#[async_trait]
+impl ITool for ListDirectory {
+    fn definition(&self) -> ToolDefinition {
+        return ToolDefinition {
+            name: "list_directory".to_string(),
+            description: "List the entries of a local directory, directories end with '/'"
+                .to_string(),
+            parameters: path_schema("Path of the directory"),
+        };
+    }
+
+    async fn call(&self, arguments: &Value) -> Result<String, String> {
+        let entries = fs::read_dir(get_string(arguments, "path")?).map_err(|e| e.to_string())?;
+        let mut names: Vec<String> = entries
+            .flatten()
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_dir() {
+                    name + "/"
+                } else {
+                    name
+                }
+            })
+            .collect();
+        names.sort();
+        return Ok(names.join("\n"));
+    }
+}
+
+struct RunCommand;
+
+#[async_trait]
+impl ITool for RunCommand {
+    fn definition(&self) -> ToolDefinition {
+        return ToolDefinition {
+            name: "run_command".to_string(),
+            description: "Run a shell command (sh -c) on the local machine, returns the exit code, stdout and stderr".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string", "description": "The command line" }
+                },
+                "required": ["command"]
+            }),
+        };
+    }
+
+    async fn call(&self, arguments: &Value) -> Result<String, String> {
+        let output = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(get_string(arguments, "command")?)
+            .output()
+            .await
+            .map_err(|e| e.to_string())?;
+        return Ok(format!(
+            "exit code: {}\nstdout:\n{}\nstderr:\n{}",
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+}
+
+struct HttpGet;
+
+#[async_trait]
+impl ITool for HttpGet {
+    fn definition(&self) -> ToolDefinition {
+        return ToolDefinition {
+            name: "http_get".to_string(),
+            description:
+                "HTTP GET on a local service (localhost only), returns the status and the body"
+                    .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "url": { "type": "string", "description": "Url, ie: http://localhost:8080/health" }
+                },
+                "required": ["url"]
+            }),
+        };
+    }
+
+    async fn call(&self, arguments: &Value) -> Result<String, String> {
+        let url = Url::parse(get_string(arguments, "url")?).map_err(|e| e.to_string())?;
+        if !is_local(&url) {
+            return Err(format!("'{}' is not a local host", url));
+        }
+        // redirects are followed on the local hosts only
+        let policy = redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if is_local(attempt.url()) {
+                attempt.follow()
+            } else {
+                let error = format!("redirect to '{}' is not a local host", attempt.url());
+                attempt.error(error)
+            }
+        });
+        let client = reqwest::Client::builder()
+            .redirect(policy)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        return Ok(format!("status: {}\n{}", status, body));
+    }
+}
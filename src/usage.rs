@@ -0,0 +1,170 @@
+use crate::{
+    ichat::Usage,
+    path::get_config_path,
+    setup::Price,
+    store::{self, Lock},
+};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use termimad::MadSkin;
+
+const USAGE_FILE: &str = "usage.jsonl";
+const LOCK_FILE: &str = "usage.lock";
+const TOKENS_PER_MILLION: f64 = 1_000_000.0;
+
+/// One line of `usage.jsonl`, kept forever (unlike the history) for reports.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UsageRecord {
+    date: NaiveDateTime,
+    chat: String,
+    model: String,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+#[derive(Default)]
+struct UsageTotal {
+    requests: u32,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: Option<f64>,
+}
+
+impl UsageTotal {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.prompt_tokens += record.prompt_tokens as u64;
+        self.completion_tokens += record.completion_tokens as u64;
+        if let Some(cost) = record.cost {
+            self.cost = Some(self.cost.unwrap_or(0.0) + cost);
+        }
+    }
+
+    fn to_row(&self, day: &str, chat: &str, model: &str) -> String {
+        return format!(
+            "| {} | {} | {} | {} | {} | {} | {} |\n",
+            day,
+            chat,
+            model,
+            self.requests,
+            self.prompt_tokens,
+            self.completion_tokens,
+            format_cost(self.cost)
+        );
+    }
+}
+
+/// Rough estimation (~4 chars per token) for backends without a tokenizer.
+pub fn estimate_tokens(text: &str) -> u32 {
+    return text.chars().count().div_ceil(4) as u32;
+}
+
+pub fn get_cost(usage: &Usage, price: Option<&Price>) -> Option<f64> {
+    let price = price?;
+    return Some(
+        (usage.prompt_tokens as f64 * price.input + usage.completion_tokens as f64 * price.output)
+            / TOKENS_PER_MILLION,
+    );
+}
+
+fn format_cost(cost: Option<f64>) -> String {
+    match cost {
+        Some(cost) => format!("${:.4}", cost),
+        None => "-".to_string(),
+    }
+}
+
+pub fn display(usage: &Usage, cost: Option<f64>) {
+    termimad::print_inline(&format!(
+        "*TOKENS* : prompt `{}` + completion `{}` = `{}` *COST* : `{}`\n",
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.total(),
+        format_cost(cost)
+    ));
+}
+
+pub fn record(
+    chat: &str,
+    model: &str,
+    usage: &Usage,
+    cost: Option<f64>,
+) -> Result<(), std::io::Error> {
+    let record = UsageRecord {
+        date: Local::now().naive_local(),
+        chat: chat.to_string(),
+        model: model.to_string(),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        cost,
+    };
+    // several `air` may record at once, lines must not interleave
+    let _lock = lock()?;
+    return store::append(
+        Path::new(&get_config_path(USAGE_FILE).path),
+        &[serde_json::to_string(&record)?],
+    );
+}
+
+fn lock() -> Result<Lock, std::io::Error> {
+    return Lock::acquire(Path::new(&get_config_path(LOCK_FILE).path));
+}
+
+fn load() -> Result<Vec<UsageRecord>, std::io::Error> {
+    let config = get_config_path(USAGE_FILE);
+    if !config.exists {
+        return Ok(vec![]);
+    }
+    let _lock = lock()?;
+    let mut records = vec![];
+    for line in String::from_utf8_lossy(&fs::read(&config.path)?).lines() {
+        match serde_json::from_str::<UsageRecord>(line) {
+            Ok(record) => records.push(record),
+            Err(e) => log::warn!("Skipping invalid usage line ({}) : {}", e, line),
+        }
+    }
+    return Ok(records);
+}
+
+/// Usage by day, backend and model.
+pub fn report() -> Result<(), std::io::Error> {
+    let records = load()?;
+    if records.is_empty() {
+        println!("No usage recorded yet.");
+        return Ok(());
+    }
+
+    let mut groups: BTreeMap<(NaiveDate, String, String), UsageTotal> = BTreeMap::new();
+    let mut total = UsageTotal::default();
+    for record in &records {
+        groups
+            .entry((
+                record.date.date(),
+                record.chat.clone(),
+                record.model.clone(),
+            ))
+            .or_default()
+            .add(record);
+        total.add(record);
+    }
+
+    let mut table = String::from(
+        "|:-|:-|:-|-:|-:|-:|-:|\n\
+         | **Day** | **Backend** | **Model** | **Requests** | **Prompt** | **Completion** | **Cost** |\n\
+         |:-|:-|:-|-:|-:|-:|-:|\n",
+    );
+    for ((day, chat, model), usage) in &groups {
+        table.push_str(&usage.to_row(&day.to_string(), chat, model));
+    }
+    table.push_str("|-|-|-|-|-|-|-|\n");
+    table.push_str(&total.to_row("**Total**", "", ""));
+    table.push_str("|-|-|-|-|-|-|-|\n");
+
+    MadSkin::default().print_text(&table);
+    Ok(())
+}
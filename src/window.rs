@@ -0,0 +1,128 @@
+use crate::{ichat::Message, usage::estimate_tokens};
+
+// the history is cut to the context window of the model : newest turns are
+// kept whole, the turn which doesn't fit is shortened, older ones are dropped
+
+/// Role and formatting tokens added to each message.
+const MESSAGE_OVERHEAD: usize = 4;
+/// A message is not shortened below this (tokens), it's dropped instead.
+const MIN_SHORTENED: usize = 64;
+const TRIMMED: &str = "\n[...]\n";
+
+/// What `fit` did to the history.
+#[derive(Debug, Default)]
+pub struct Trim {
+    pub window: usize,
+    pub reserved: usize,
+    /// Room left for the history.
+    pub budget: usize,
+    pub before: usize,
+    pub after: usize,
+    pub dropped: usize,
+    pub shortened: usize,
+}
+
+impl Trim {
+    pub fn report(&self) {
+        if self.budget == 0 {
+            log::warn!(
+                "System and prompt alone don't fit in the context window ({} tokens, {} reserved)",
+                self.window,
+                self.reserved
+            );
+        }
+        if self.dropped == 0 && self.shortened == 0 {
+            log::debug!(
+                "History    : ~{} tokens, {} available",
+                self.before,
+                self.budget
+            );
+            return;
+        }
+        log::info!(
+            "History    : {} turn(s) dropped, {} shortened, ~{} -> ~{} tokens (window {}, {} reserved)",
+            self.dropped,
+            self.shortened,
+            self.before,
+            self.after,
+            self.window,
+            self.reserved
+        );
+    }
+}
+
+fn count(messages: &[Message]) -> usize {
+    return messages
+        .iter()
+        .map(|m| estimate_tokens(&m.content.text()) as usize + MESSAGE_OVERHEAD)
+        .sum();
+}
+
+/// Keep the beginning and the end of a message.
+fn shorten(message: &Message, tokens: usize) -> Message {
+    let chars: Vec<char> = message.content.text().chars().collect();
+    let keep = (tokens * 4).saturating_sub(TRIMMED.len());
+    let head: String = chars[..keep * 2 / 3].iter().collect();
+    let tail: String = chars[chars.len() - (keep - keep * 2 / 3)..]
+        .iter()
+        .collect();
+    return Message::new(message.role.clone(), format!("{}{}{}", head, TRIMMED, tail));
+}
+
+/// History (user/assistant pairs) fitting in the window with the system
+/// prompt, the prompt and the tokens reserved for the answer.
+pub fn fit(
+    history: Vec<Message>,
+    system: &str,
+    prompt: &str,
+    window: usize,
+    reserved: usize,
+) -> (Vec<Message>, Trim) {
+    let fixed =
+        estimate_tokens(system) as usize + estimate_tokens(prompt) as usize + 2 * MESSAGE_OVERHEAD;
+    // a guessed window can be smaller than the reserve (llama-cpp default)
+    let reserved = reserved.min(window / 4);
+    let mut trim = Trim {
+        window,
+        reserved,
+        budget: window.saturating_sub(reserved + fixed),
+        before: count(&history),
+        ..Default::default()
+    };
+    if trim.before <= trim.budget {
+        trim.after = trim.before;
+        return (history, trim);
+    }
+
+    let turns: Vec<&[Message]> = history.chunks(2).collect();
+    let mut kept: Vec<Vec<Message>> = vec![];
+    let mut used: usize = 0;
+    for turn in turns.iter().rev() {
+        let cost = count(turn);
+        if used + cost <= trim.budget {
+            used += cost;
+            kept.push(turn.to_vec());
+            continue;
+        }
+        // shorten the long messages of the turn to the room left
+        let room = (trim.budget - used) / turn.len();
+        if room >= MIN_SHORTENED + MESSAGE_OVERHEAD {
+            let mut shortened = vec![];
+            for message in turn.iter() {
+                if estimate_tokens(&message.content.text()) as usize + MESSAGE_OVERHEAD > room {
+                    shortened.push(shorten(message, room - MESSAGE_OVERHEAD));
+                    trim.shortened += 1;
+                } else {
+                    shortened.push(message.clone());
+                }
+            }
+            used += count(&shortened);
+            kept.push(shortened);
+        }
+        break;
+    }
+    trim.dropped = turns.len() - kept.len();
+    trim.after = used;
+    kept.reverse();
+    return (kept.into_iter().flatten().collect(), trim);
+}